@@ -1,6 +1,11 @@
+mod audit;
+mod dashboard;
 mod error_box;
 mod form;
 mod home;
+mod link;
+mod login;
+mod retrieval;
 mod user_home;
 
 use leptos::prelude::*;
@@ -51,8 +56,12 @@ pub fn App() -> impl IntoView {
             <main>
                 <Routes fallback=|| "Page not found.".into_view()>
                     <Route path=path!("/") view=home::Page/>
-                    <Route path=path!("/tests/:user") view=user_home::Page/>
-                    <Route path=path!("/tests/:user/:test") view=form::Page/>
+                    <Route path=path!("/login") view=login::Page/>
+                    <Route path=path!("/patienten") view=dashboard::Page/>
+                    <Route path=path!("/protokoll") view=audit::Page/>
+                    <Route path=path!("/tests/abruf/:retrieval_id") view=retrieval::Page/>
+                    <Route path=path!("/tests/:token") view=user_home::Page/>
+                    <Route path=path!("/tests/:token/:test") view=form::Page/>
                 </Routes>
             </main>
         </Router>
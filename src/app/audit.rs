@@ -0,0 +1,113 @@
+use crate::app::error_box::ErrorBox;
+use crate::app::login::StaffBar;
+use crate::app::retrieval::format_date;
+use crate::app::ServerFnResult;
+use crate::types::*;
+use chrono::NaiveDate;
+use leptos::prelude::*;
+use leptos_router::hooks::use_query_map;
+
+#[server]
+async fn list_audit(filter: AuditFilter) -> ServerFnResult<Vec<AuditEntry>> {
+    crate::auth::require_staff().await?;
+    crate::db::Db::get()
+        .list_audit(filter)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+/// The audit log, optionally narrowed to the patient given in the `patient` query parameter.
+#[component]
+pub(crate) fn Page() -> impl IntoView {
+    let query = use_query_map();
+    let patient = RwSignal::new(query.read_untracked().get("patient").unwrap_or_default());
+    let from = RwSignal::new(None::<NaiveDate>);
+    let to = RwSignal::new(None::<NaiveDate>);
+    let entries = Resource::new(
+        move || AuditFilter {
+            patient: patient.get(),
+            from: from.get(),
+            to: to.get(),
+        },
+        list_audit,
+    );
+
+    view! {
+        <StaffBar />
+        <h2>"Zugriffsprotokoll"</h2>
+        <div class="audit-filter">
+            <label>
+                <span>"Patienten ID"</span>
+                <input
+                    type="search"
+                    prop:value=patient
+                    on:input=move |ev| patient.set(event_target_value(&ev))
+                />
+            </label>
+            <label>
+                <span>"Von"</span>
+                <input
+                    type="date"
+                    on:change=move |ev| from.set(event_target_value(&ev).parse().ok())
+                />
+            </label>
+            <label>
+                <span>"Bis"</span>
+                <input
+                    type="date"
+                    on:change=move |ev| to.set(event_target_value(&ev).parse().ok())
+                />
+            </label>
+        </div>
+        <ErrorBox>
+            <Transition fallback=move || {
+                "Loading..."
+            }>
+                {Suspend::new(async move {
+                    entries
+                        .await
+                        .map(|entries| {
+                            view! {
+                                <table class="audit">
+                                    <tr>
+                                        <th>"Zeitpunkt"</th>
+                                        <th>"Mitarbeiter"</th>
+                                        <th>"Aktion"</th>
+                                        <th>"Patienten ID"</th>
+                                        <th>"Details"</th>
+                                    </tr>
+                                    {entries
+                                        .into_iter()
+                                        .map(|entry| view! { <AuditRow entry /> })
+                                        .collect_view()}
+                                </table>
+                            }
+                        })
+                })}
+            </Transition>
+        </ErrorBox>
+    }
+}
+
+#[component]
+fn AuditRow(entry: AuditEntry) -> impl IntoView {
+    let AuditEntry {
+        at,
+        actor,
+        action,
+        patient,
+        details,
+    } = entry;
+
+    view! {
+        <tr>
+            <td>{format_date(&at)}</td>
+            <td>{actor.unwrap_or_else(|| String::from("System"))}</td>
+            <td>{action.label()}</td>
+            <td>
+                <code>{patient.unwrap_or_else(|| String::from("–"))}</code>
+            </td>
+            <td>{details}</td>
+        </tr>
+    }
+}
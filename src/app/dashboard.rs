@@ -0,0 +1,132 @@
+use crate::app::error_box::ErrorBox;
+use crate::app::login::StaffBar;
+use crate::app::retrieval::format_date;
+use crate::app::ServerFnResult;
+use crate::types::*;
+use leptos::prelude::*;
+
+#[server]
+async fn list_patients(filter: PatientFilter) -> ServerFnResult<Vec<PatientOverview>> {
+    crate::auth::require_staff().await?;
+    crate::db::Db::get()
+        .list_patients(filter)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+#[component]
+pub(crate) fn Page() -> impl IntoView {
+    let case_number = RwSignal::new(String::new());
+    let progress = RwSignal::new(ProgressFilter::All);
+    let patients = Resource::new(
+        move || PatientFilter {
+            case_number: case_number.get(),
+            progress: progress.get(),
+        },
+        list_patients,
+    );
+
+    view! {
+        <StaffBar />
+        <h2>"Patienten"</h2>
+        <div class="patient-filter">
+            <label>
+                <span>"Fallnummer"</span>
+                <input
+                    type="search"
+                    prop:value=case_number
+                    on:input=move |ev| case_number.set(event_target_value(&ev))
+                />
+            </label>
+            <label>
+                <span>"Status"</span>
+                <select on:change=move |ev| {
+                    progress
+                        .set(
+                            match event_target_value(&ev).as_str() {
+                                "finished" => ProgressFilter::Finished,
+                                "unfinished" => ProgressFilter::Unfinished,
+                                _ => ProgressFilter::All,
+                            },
+                        );
+                }>
+                    <option value="all">"Alle"</option>
+                    <option value="unfinished">"Offen"</option>
+                    <option value="finished">"Abgeschlossen"</option>
+                </select>
+            </label>
+        </div>
+        <ErrorBox>
+            <Transition fallback=move || {
+                "Loading..."
+            }>
+                {Suspend::new(async move {
+                    patients
+                        .await
+                        .map(|patients| {
+                            view! {
+                                <table class="patients">
+                                    <tr>
+                                        <th>"Fallnummer"</th>
+                                        <th>"Erstellt"</th>
+                                        <th>"Tests"</th>
+                                        <th>"Status"</th>
+                                        <th>"Links"</th>
+                                    </tr>
+                                    {patients
+                                        .into_iter()
+                                        .map(|patient| view! { <PatientRow patient /> })
+                                        .collect_view()}
+                                </table>
+                            }
+                        })
+                })}
+            </Transition>
+        </ErrorBox>
+    }
+}
+
+#[component]
+fn PatientRow(patient: PatientOverview) -> impl IntoView {
+    let finished = patient.is_finished();
+    let PatientOverview { user, tests } = patient;
+    let expired = user.link_expired(chrono::Utc::now());
+    let case_number = user.info.case_number.unwrap_or_else(|| String::from("–"));
+    let created_at = user
+        .created_at
+        .as_ref()
+        .map_or_else(|| String::from("–"), format_date);
+    let tests = tests
+        .into_iter()
+        .map(|progress| {
+            let state = if progress.submitted {
+                String::from("abgeschickt")
+            } else {
+                format!("{}/{}", progress.answered, progress.total)
+            };
+            view! {
+                <li>
+                    {progress.test.name} " " <span class="badge">{state}</span>
+                </li>
+            }
+        })
+        .collect_view();
+    let patienten_href = format!("/tests/{}", user.access_token);
+    let abruf_href = format!("/tests/abruf/{}", user.retrieval_id);
+
+    view! {
+        <tr class:finished=finished>
+            <td>{case_number}</td>
+            <td>{created_at}</td>
+            <td>
+                <ul>{tests}</ul>
+            </td>
+            <td>{if finished { "Abgeschlossen" } else { "Offen" }}</td>
+            <td>
+                {(!expired).then(|| view! { <a href=patienten_href>"Patienten Link"</a> })}
+                " "
+                <a href=abruf_href>"Abruf Link"</a>
+            </td>
+        </tr>
+    }
+}
@@ -1,33 +1,36 @@
 use leptos_router::hooks::use_params_map;
 
+use super::link::ValidLink;
 use super::ServerFnResult;
 use crate::types::*;
-use leptos::{
-    leptos_dom::logging::{console_error, console_log},
-    prelude::*,
-    task::spawn_local,
-};
+use leptos::{leptos_dom::logging::console_error, prelude::*};
+use std::collections::BTreeMap;
 
 #[server]
-async fn get_questions(user: String) -> ServerFnResult<Vec<Question>> {
-    crate::db::Db::get()
-        .get_questions()
+async fn get_questions(token: String, test: String) -> ServerFnResult<AnsweredQuestionnaire> {
+    let db = crate::db::Db::get();
+    let user = db
+        .get_user_by_access_token(token)
+        .await
+        .map_err(ServerFnError::new)?;
+    db.get_answered_questions(user.id, test)
         .await
         .map_err(ServerFnError::new)
 }
 
+/// Stores the given answers without finishing the test, used to autosave every change.
 #[server]
-async fn submit(data: Data) -> ServerFnResult<()> {
+async fn save_answers(data: Data) -> ServerFnResult<()> {
     crate::db::Db::get()
-        .submit_test(data)
+        .submit_test(data, false)
         .await
         .map_err(ServerFnError::new)
 }
 
 #[server]
-async fn eval_test(user_id: String) -> ServerFnResult<Vec<TestResultRecord>> {
+async fn submit(data: Data) -> ServerFnResult<()> {
     crate::db::Db::get()
-        .evaluate_test(user_id)
+        .submit_test(data, true)
         .await
         .map_err(ServerFnError::new)
 }
@@ -35,102 +38,224 @@ async fn eval_test(user_id: String) -> ServerFnResult<Vec<TestResultRecord>> {
 #[component]
 pub(crate) fn Page() -> impl IntoView {
     let params = use_params_map();
-    let user_id = Signal::derive(move || params.read().get("user").and_then(|id| id.parse().ok()));
+    let token = Signal::derive(move || params.read().get("token"));
+    let test_id = Signal::derive(move || params.read().get("test"));
     let resource = Resource::new(
-        move || user_id(),
-        |id| async move {
-            if let Some(id) = id {
-                get_questions(id).await
-            } else {
-                Err(ServerFnError::new("invalid user ID"))
+        move || (token(), test_id()),
+        |ids| async move {
+            match ids {
+                (Some(token), Some(test)) => get_questions(token, test).await,
+                (None, _) => Err(ServerFnError::new("invalid link")),
+                (_, None) => Err(ServerFnError::new("invalid test ID")),
             }
         },
     );
 
+    let autosave_action = Action::new(move |data: &Data| {
+        let data = data.clone();
+        async move { save_answers(data).await }
+    });
+    let autosave_status = move || match (
+        autosave_action.pending().get(),
+        autosave_action.value().get(),
+    ) {
+        (true, _) => Some(String::from("Wird gespeichert …")),
+        (false, Some(Ok(()))) => Some(String::from("Alle Antworten gespeichert")),
+        (false, Some(Err(err))) => Some(format!("Speichern fehlgeschlagen: {err}")),
+        (false, None) => None,
+    };
+
     let submit_action = Action::new(move |data: &Data| {
         let data = data.clone();
         async move { submit(data).await }
     });
-
-    view! {
-        <h2>"Fragebogen AQ"</h2>
-        <form on:submit=move|ev| {
-            ev.prevent_default();
-            ev.stop_propagation();
-            match Data::from_event(&ev) {
-                Ok(data) => {
-                    submit_action.dispatch(data);
-                }
-                Err(err) => {
-                    console_error(&format!("{err:?}"));
-                }
-            }
-        }>
-            <input type="hidden" name="user[id]" value=user_id() />
-            <ErrorBoundary fallback=|errors| {
+    let submitted = move || matches!(submit_action.value().get(), Some(Ok(())));
+    let form_error = RwSignal::new(None::<String>);
+    let submit_error = move || {
+        form_error
+            .get()
+            .or_else(|| match submit_action.value().get() {
+                Some(Err(err)) => Some(err.to_string()),
+                _ => None,
+            })
+            .map(|err| {
                 view! {
                     <pre class="error">
-                        <p>"Errors: "</p>
-                        <ul>
-                            {move || {
-                                errors
-                                    .get()
-                                    .into_iter()
-                                    .map(|(_, e)| view! { <li>{e.to_string()}</li> })
-                                    .collect::<Vec<_>>()
-                            }}
-                        </ul>
+                        <p>"Der Fragebogen konnte nicht abgeschickt werden:"</p>
+                        {err}
                     </pre>
                 }
-            }>
-                <Suspense fallback=move || {
-                    view! { <p>"Loading..."</p> }
+            })
+    };
+
+    view! {
+        <ValidLink>
+            <Show when=move || !submitted() fallback=move || view! { <ThankYou token /> }>
+                <form on:submit=move|ev| {
+                    ev.prevent_default();
+                    ev.stop_propagation();
+                    match Data::from_event(&ev) {
+                        Ok(data) => {
+                            form_error.set(None);
+                            submit_action.dispatch(data);
+                        }
+                        Err(err) => {
+                            console_error(&format!("{err:?}"));
+                            form_error.set(Some(err.to_string()));
+                        }
+                    }
                 }>
-                    {Suspend::new(async move {
-                        resource
-                            .await
-                            .map(|q: Vec<Question>| {
-                                q.into_iter()
-                                    .map(|q| view! { <QuestionElement question=q /> })
-                                    .collect_view()
-                            })
-                    })}
-                </Suspense>
+                    <input type="hidden" name="user[token]" value=token() />
+                    <ErrorBoundary fallback=|errors| {
+                        view! {
+                            <pre class="error">
+                                <p>"Errors: "</p>
+                                <ul>
+                                    {move || {
+                                        errors
+                                            .get()
+                                            .into_iter()
+                                            .map(|(_, e)| view! { <li>{e.to_string()}</li> })
+                                            .collect::<Vec<_>>()
+                                    }}
+                                </ul>
+                            </pre>
+                        }
+                    }>
+                        <Suspense fallback=move || {
+                            view! { <p>"Loading..."</p> }
+                        }>
+                            {Suspend::new(async move {
+                                let AnsweredQuestionnaire {
+                                    test,
+                                    questions,
+                                    submitted,
+                                } = resource.await?;
+                                let version = TestVersion {
+                                    id: test.id.clone(),
+                                    version: test.version,
+                                };
+                                let on_change = Callback::new(move |(question, choice): (String, usize)| {
+                                    let Some(token) = token.get_untracked() else {
+                                        return;
+                                    };
+                                    autosave_action
+                                        .dispatch(Data {
+                                            user: UserRef { token },
+                                            test: version.clone(),
+                                            q: BTreeMap::from([(question, choice)]),
+                                        });
+                                });
+                                Ok::<_, ServerFnError>(
+                                    view! {
+                                        <h2>"Fragebogen " {test.name}</h2>
+                                        <input type="hidden" name="test[id]" value=test.id />
+                                        <input type="hidden" name="test[version]" value=test.version />
+                                        {submitted
+                                            .then(|| {
+                                                view! {
+                                                    <p class="read-only">
+                                                        "Dieser Fragebogen wurde bereits abgeschickt und kann nicht mehr geändert werden."
+                                                    </p>
+                                                }
+                                            })}
+                                        {questions
+                                            .into_iter()
+                                            .map(|AnsweredQuestion { question, choice }| {
+                                                view! {
+                                                    <QuestionElement question choice disabled=submitted on_change />
+                                                }
+                                            })
+                                            .collect_view()}
+                                        {(!submitted)
+                                            .then(|| {
+                                                view! {
+                                                    <button class="btn" disabled=move || submit_action.pending().get()>
+                                                        {move || {
+                                                            if submit_action.pending().get() {
+                                                                "Wird abgeschickt …"
+                                                            } else {
+                                                                "Fragebogen abschicken"
+                                                            }
+                                                        }}
+                                                    </button>
+                                                }
+                                            })}
+                                    },
+                                )
+                            })}
+                        </Suspense>
+
+                    </ErrorBoundary>
+                    <p class="autosave">{autosave_status}</p>
+                    {submit_error}
+                </form>
+            </Show>
+        </ValidLink>
+    }
+}
 
-            </ErrorBoundary>
-            <button class="btn">Speichern</button>
-        </form>
-            // <button class="btn" on:click=move |_| {
-            //     if let Some(id) = user_id.get_untracked() {
-            //         spawn_local(async move {
-            //     let res = eval_test(id).await;
-            //             console_log(&format!("{:?}", res));
-            //         });
-            //     }
-            // }>Eval</button>
+#[component]
+fn ThankYou(token: Signal<Option<String>>) -> impl IntoView {
+    view! {
+        <section class="thank-you">
+            <h2>"Vielen Dank!"</h2>
+            <p>"Ihre Antworten wurden übermittelt."</p>
+            {move || {
+                token()
+                    .map(|token| {
+                        view! {
+                            <a class="btn" href=format!("/tests/{token}")>
+                                "Zurück zur Übersicht"
+                            </a>
+                        }
+                    })
+            }}
+        </section>
     }
 }
 
 #[component]
-fn QuestionElement(question: Question) -> impl IntoView {
+fn QuestionElement(
+    question: Question,
+    /// The previously saved choice, selected when the form is loaded.
+    choice: Option<usize>,
+    disabled: bool,
+    on_change: Callback<(String, usize)>,
+) -> impl IntoView {
     let Question {
         prompt,
         id,
         options,
+        ..
     } = question;
 
     let options = options
         .into_iter()
         .enumerate()
         .map(|(index, o)| {
-            view! { <option value=index>{o.label}</option> }
+            view! {
+                <option value=index selected=choice == Some(index)>
+                    {o.label}
+                </option>
+            }
         })
         .collect_view();
+    let name = format!("q[{id}]");
 
     view! {
         <label>
             <span>{prompt}</span>
-            <select required name=format!("q[{id}]")>
+            <select
+                required
+                disabled=disabled
+                name=name
+                on:change=move |ev| {
+                    if let Ok(choice) = event_target_value(&ev).parse::<usize>() {
+                        on_change.run((id.clone(), choice));
+                    }
+                }
+            >
                 <option value=""></option>
                 {options}
             </select>
@@ -1,23 +1,26 @@
 use std::collections::BTreeSet;
 
 use crate::app::error_box::ErrorBox;
+use crate::app::login::StaffBar;
 use crate::types::*;
-use leptos::leptos_dom::logging::console_log;
+use chrono::Datelike;
 use leptos::prelude::*;
 
 use super::ServerFnResult;
 
 #[server]
-async fn create_user(tests: BTreeSet<String>) -> ServerFnResult<User> {
+async fn create_user(new_user: NewUser) -> ServerFnResult<User> {
+    let staff = crate::auth::require_staff().await?;
     let user = crate::db::Db::get()
-        .create_user(tests)
+        .create_user(new_user, &staff)
         .await
         .map_err(ServerFnError::new)?;
     Ok(user)
 }
 
 #[server]
-async fn get_available_tests() -> ServerFnResult<Vec<Test>> {
+pub(crate) async fn get_available_tests() -> ServerFnResult<Vec<Test>> {
+    crate::auth::require_staff().await?;
     let user = crate::db::Db::get()
         .get_available_tests()
         .await
@@ -28,16 +31,73 @@ async fn get_available_tests() -> ServerFnResult<Vec<Test>> {
 #[derive(serde::Deserialize, Clone, Debug)]
 struct FormData {
     tests: BTreeSet<String>,
+    /// `0` keeps the link valid indefinitely.
+    valid_days: u32,
+    #[serde(default)]
+    expire_when_done: bool,
+    #[serde(default)]
+    case_number: String,
+    #[serde(default)]
+    birth_year: String,
+    #[serde(default)]
+    sex: String,
+    #[serde(default)]
+    clinician: String,
+}
+
+/// Birth years the database accepts, see the patient details migration.
+fn birth_years() -> std::ops::RangeInclusive<i32> {
+    1900..=chrono::Utc::now().year()
+}
+
+impl TryFrom<FormData> for NewUser {
+    type Error = String;
+
+    fn try_from(data: FormData) -> Result<Self, Self::Error> {
+        let text = |value: String| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        let sex = match data.sex.as_str() {
+            "female" => Some(Sex::Female),
+            "male" => Some(Sex::Male),
+            "diverse" => Some(Sex::Diverse),
+            _ => None,
+        };
+        let years = birth_years();
+        let birth_year = match data.birth_year.trim() {
+            "" => None,
+            year => match year.parse() {
+                Ok(year) if years.contains(&year) => Some(year),
+                _ => {
+                    return Err(format!(
+                        "Das Geburtsjahr muss zwischen {} und {} liegen",
+                        years.start(),
+                        years.end()
+                    ))
+                }
+            },
+        };
+        Ok(NewUser {
+            tests: data.tests,
+            valid_days: (data.valid_days > 0).then_some(data.valid_days),
+            expire_when_done: data.expire_when_done,
+            info: PatientInfo {
+                case_number: text(data.case_number),
+                birth_year,
+                sex,
+                clinician: text(data.clinician),
+            },
+        })
+    }
 }
 
 #[component]
 pub(crate) fn Page() -> impl IntoView {
-    let create_user_action = Action::new(|tests: &BTreeSet<String>| {
-        let tests = tests.clone();
-        async move { create_user(tests).await }
+    let create_user_action = Action::new(|new_user: &NewUser| {
+        let new_user = new_user.clone();
+        async move { create_user(new_user).await }
     });
+    let form_error = RwSignal::new(None::<String>);
     let available_tests = Resource::new(|| (), |_| async move { get_available_tests().await });
-    let make_test_opt = |(index, Test { id, name }): (usize, Test)| {
+    let make_test_opt = |(index, Test { id, name, .. }): (usize, Test)| {
         view! {
             <label>
                 <input type="checkbox" name=format!("tests[{index}]") value=id />
@@ -46,22 +106,36 @@ pub(crate) fn Page() -> impl IntoView {
         }
     };
 
-    let user_links = |User { id, retrieval_id }| {
-        let patienten_href = format!("/tests/{}", id);
-        let abruf_href = format!("/tests/abruf/{}", retrieval_id);
+    let user_links = |user: User| {
+        let patienten_href = format!("/tests/{}", user.access_token);
+        let abruf_href = format!("/tests/abruf/{}", user.retrieval_id);
+        let valid_until = user
+            .access_expires_at
+            .map(|date| format!("gültig bis {}", date.format("%d.%m.%Y")))
+            .unwrap_or_else(|| String::from("unbegrenzt gültig"));
         view! {
             <a href=patienten_href>Patienten Link</a>
+            " (" {valid_until} ") "
             <a href=abruf_href>Abruf Link</a>
         }
     };
 
     view! {
+        <StaffBar />
         <ErrorBox>
             <form on:submit=move |ev| {
                 ev.prevent_default();
                 ev.stop_propagation();
-                let data = FormData::from_event(&ev);
-                create_user_action.dispatch(data.unwrap().tests);
+                match FormData::from_event(&ev)
+                    .map_err(|err| err.to_string())
+                    .and_then(NewUser::try_from)
+                {
+                    Ok(new_user) => {
+                        form_error.set(None);
+                        create_user_action.dispatch(new_user);
+                    }
+                    Err(err) => form_error.set(Some(err)),
+                }
             }>
                 <Suspense fallback=move || {
                     "Loading..."
@@ -74,6 +148,51 @@ pub(crate) fn Page() -> impl IntoView {
                             })
                     })}
                 </Suspense>
+                <fieldset class="patient-info">
+                    <legend>"Angaben zum Patienten (optional)"</legend>
+                    <label>
+                        <span>"Fallnummer"</span>
+                        <input name="case_number" autocomplete="off" />
+                    </label>
+                    <label>
+                        <span>"Geburtsjahr"</span>
+                        <input
+                            type="number"
+                            name="birth_year"
+                            min=birth_years().start().to_string()
+                            max=birth_years().end().to_string()
+                        />
+                    </label>
+                    <label>
+                        <span>"Geschlecht"</span>
+                        <select name="sex">
+                            <option value=""></option>
+                            <option value="female">{Sex::Female.label()}</option>
+                            <option value="male">{Sex::Male.label()}</option>
+                            <option value="diverse">{Sex::Diverse.label()}</option>
+                        </select>
+                    </label>
+                    <label>
+                        <span>"Behandler/in"</span>
+                        <input name="clinician" />
+                    </label>
+                </fieldset>
+                <label>
+                    <span>"Gültigkeit des Links"</span>
+                    <select name="valid_days">
+                        <option value="7">"7 Tage"</option>
+                        <option value="30" selected>"30 Tage"</option>
+                        <option value="90">"90 Tage"</option>
+                        <option value="0">"Unbegrenzt"</option>
+                    </select>
+                </label>
+                <label>
+                    <input type="checkbox" name="expire_when_done" value="true" checked />
+                    <span>"Link sperren, sobald alle Tests abgeschickt sind"</span>
+                </label>
+                {move || {
+                    form_error.get().map(|err| view! { <pre class="error">{err}</pre> })
+                }}
                 <button class="btn">Generieren</button>
 
             </form>
@@ -0,0 +1,70 @@
+use super::ServerFnResult;
+use crate::types::*;
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+
+#[server]
+async fn get_link_status(token: String) -> ServerFnResult<LinkStatus> {
+    crate::db::Db::get()
+        .get_link_status(token)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+/// Renders its children only while the patient link in the `token` route parameter is valid,
+/// and explains to the patient why it cannot be used otherwise.
+#[component]
+pub(crate) fn ValidLink(children: ChildrenFn) -> impl IntoView {
+    let params = use_params_map();
+    let token = Signal::derive(move || params.read().get("token"));
+    let status = Resource::new(
+        move || token.get(),
+        |token| async move {
+            match token {
+                Some(token) => get_link_status(token).await,
+                None => Ok(LinkStatus::Unknown),
+            }
+        },
+    );
+
+    view! {
+        <Suspense fallback=move || {
+            view! { <p>"Loading..."</p> }
+        }>
+            {move || {
+                let children = children.clone();
+                Suspend::new(async move {
+                    match status.await {
+                        Ok(LinkStatus::Valid) => children().into_any(),
+                        Ok(LinkStatus::Expired) => {
+                            view! {
+                                <section class="link-message">
+                                    <h2>"Dieser Link ist nicht mehr gültig"</h2>
+                                    <p>
+                                        "Zum Schutz Ihrer Daten sind unsere Fragebogen-Links nur begrenzt gültig. "
+                                        "Falls Sie noch Fragebögen ausfüllen möchten, wenden Sie sich bitte an die Praxis, "
+                                        "wir schicken Ihnen gerne einen neuen Link."
+                                    </p>
+                                </section>
+                            }
+                                .into_any()
+                        }
+                        Ok(LinkStatus::Unknown) => {
+                            view! {
+                                <section class="link-message">
+                                    <h2>"Dieser Link ist ungültig"</h2>
+                                    <p>
+                                        "Bitte prüfen Sie, ob Sie den Link vollständig übernommen haben, "
+                                        "oder wenden Sie sich an die Praxis."
+                                    </p>
+                                </section>
+                            }
+                                .into_any()
+                        }
+                        Err(err) => view! { <pre class="error">{err.to_string()}</pre> }.into_any(),
+                    }
+                })
+            }}
+        </Suspense>
+    }
+}
@@ -0,0 +1,111 @@
+use super::ServerFnResult;
+use crate::types::*;
+use leptos::prelude::*;
+use leptos_router::components::Redirect;
+
+#[server]
+async fn login(username: String, password: String) -> ServerFnResult<()> {
+    let staff = crate::auth::login(username, password)
+        .await
+        .map_err(ServerFnError::new)?;
+    if staff.is_none() {
+        return Err(ServerFnError::new("Benutzername oder Passwort ist falsch"));
+    }
+    leptos_axum::redirect("/");
+    Ok(())
+}
+
+#[server]
+async fn logout() -> ServerFnResult<()> {
+    crate::auth::logout().await.map_err(ServerFnError::new)?;
+    leptos_axum::redirect("/login");
+    Ok(())
+}
+
+#[server]
+async fn current_staff() -> ServerFnResult<Option<Staff>> {
+    Ok(crate::auth::current_staff().await)
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+#[component]
+pub(crate) fn Page() -> impl IntoView {
+    let login_action = Action::new(|credentials: &Credentials| {
+        let Credentials { username, password } = credentials.clone();
+        async move { login(username, password).await }
+    });
+    let login_error = move || {
+        login_action
+            .value()
+            .get()
+            .and_then(|res| res.err())
+            .map(|err| view! { <pre class="error">{err.to_string()}</pre> })
+    };
+
+    view! {
+        <h2>"Anmeldung"</h2>
+        <form on:submit=move |ev| {
+            ev.prevent_default();
+            ev.stop_propagation();
+            if let Ok(credentials) = Credentials::from_event(&ev) {
+                login_action.dispatch(credentials);
+            }
+        }>
+            <label>
+                <span>"Benutzername"</span>
+                <input name="username" required autocomplete="username" />
+            </label>
+            <label>
+                <span>"Passwort"</span>
+                <input type="password" name="password" required autocomplete="current-password" />
+            </label>
+            {login_error}
+            <button class="btn" disabled=move || login_action.pending().get()>
+                "Anmelden"
+            </button>
+        </form>
+    }
+}
+
+/// Shows who is logged in, and sends visitors without a staff session to the login page.
+#[component]
+pub(crate) fn StaffBar() -> impl IntoView {
+    let staff = Resource::new(|| (), |_| current_staff());
+    let logout_action = Action::new(|_: &()| logout());
+
+    view! {
+        <Suspense>
+            {Suspend::new(async move {
+                match staff.await {
+                    Ok(Some(Staff { username })) => {
+                        view! {
+                            <div class="staff-bar">
+                                <nav>
+                                    <a href="/">"Neuer Patient"</a>
+                                    <a href="/patienten">"Patienten"</a>
+                                    <a href="/protokoll">"Protokoll"</a>
+                                </nav>
+                                <span>"Angemeldet als " <b>{username}</b></span>
+                                <button
+                                    class="btn"
+                                    on:click=move |_| {
+                                        logout_action.dispatch(());
+                                    }
+                                >
+                                    "Abmelden"
+                                </button>
+                            </div>
+                        }
+                            .into_any()
+                    }
+                    _ => view! { <Redirect path="/login" /> }.into_any(),
+                }
+            })}
+        </Suspense>
+    }
+}
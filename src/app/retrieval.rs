@@ -0,0 +1,478 @@
+use crate::app::error_box::ErrorBox;
+use crate::app::home::get_available_tests;
+use crate::app::login::StaffBar;
+use crate::app::ServerFnResult;
+use crate::types::*;
+// Shadows `leptos::prelude::Submission`.
+use crate::types::Submission;
+use chrono::{DateTime, Utc};
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+use uuid::Uuid;
+
+#[server]
+async fn get_retrieval(retrieval_id: Uuid) -> ServerFnResult<Retrieval> {
+    let staff = crate::auth::require_staff().await?;
+    crate::db::Db::get()
+        .get_retrieval(retrieval_id, &staff)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+#[server]
+async fn create_report(retrieval_id: Uuid, test_id: String) -> ServerFnResult<Evaluation> {
+    let staff = crate::auth::require_staff().await?;
+    let db = crate::db::Db::get();
+    let user = db
+        .get_user_by_retrieval_id(retrieval_id)
+        .await
+        .map_err(ServerFnError::new)?;
+    db.evaluate_test(user.id, test_id, &staff)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+#[server]
+async fn lock_submission(retrieval_id: Uuid, test_id: String) -> ServerFnResult<Submission> {
+    crate::auth::require_staff().await?;
+    let db = crate::db::Db::get();
+    let user = db
+        .get_user_by_retrieval_id(retrieval_id)
+        .await
+        .map_err(ServerFnError::new)?;
+    db.lock_submission(user.id, test_id)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+#[server]
+async fn assign_test(retrieval_id: Uuid, test_id: String) -> ServerFnResult<()> {
+    crate::auth::require_staff().await?;
+    let db = crate::db::Db::get();
+    let user = db
+        .get_user_by_retrieval_id(retrieval_id)
+        .await
+        .map_err(ServerFnError::new)?;
+    db.assign_test(user.id, test_id)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+/// Removes a test from the patient. Tests with answers or reports are only removed with `force`.
+#[server]
+async fn unassign_test(retrieval_id: Uuid, test_id: String, force: bool) -> ServerFnResult<()> {
+    crate::auth::require_staff().await?;
+    let db = crate::db::Db::get();
+    let user = db
+        .get_user_by_retrieval_id(retrieval_id)
+        .await
+        .map_err(ServerFnError::new)?;
+    db.unassign_test(user.id, test_id, force)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+/// Deletes or anonymizes the patient and returns to the patient list.
+#[server]
+async fn erase_patient(retrieval_id: Uuid, erasure: Erasure) -> ServerFnResult<()> {
+    let staff = crate::auth::require_staff().await?;
+    let db = crate::db::Db::get();
+    let user = db
+        .get_user_by_retrieval_id(retrieval_id)
+        .await
+        .map_err(ServerFnError::new)?;
+    let erased = db
+        .erase_user(user.id.clone(), erasure)
+        .await
+        .map_err(ServerFnError::new)?;
+    let ErasedData {
+        answers, reports, ..
+    } = erased;
+    let note = erased.undeleted_files_note();
+    let (action, details) = match erasure {
+        Erasure::Delete => (
+            AuditAction::PatientDeleted,
+            format!("Patient, {answers} Antworten und {reports} Berichte gelöscht{note}"),
+        ),
+        Erasure::Anonymize => (
+            AuditAction::PatientAnonymized,
+            format!(
+                "Patientendaten und {reports} Berichte gelöscht, Antworten anonym behalten{note}"
+            ),
+        ),
+    };
+    db.audit(Some(staff.username), action, Some(user.id), details)
+        .await
+        .map_err(ServerFnError::new)?;
+    leptos_axum::redirect("/patienten");
+    Ok(())
+}
+
+#[component]
+pub(crate) fn Page() -> impl IntoView {
+    let params = use_params_map();
+    let retrieval_id = Signal::derive(move || {
+        params
+            .read()
+            .get("retrieval_id")
+            .and_then(|id| id.parse::<Uuid>().ok())
+    });
+
+    let create_report_action = Action::new(move |test_id: &String| {
+        let test_id = test_id.clone();
+        async move {
+            match retrieval_id.get_untracked() {
+                Some(id) => create_report(id, test_id).await,
+                None => Err(ServerFnError::new("Invalid retrieval ID")),
+            }
+        }
+    });
+
+    let lock_action = Action::new(move |test_id: &String| {
+        let test_id = test_id.clone();
+        async move {
+            match retrieval_id.get_untracked() {
+                Some(id) => lock_submission(id, test_id).await,
+                None => Err(ServerFnError::new("Invalid retrieval ID")),
+            }
+        }
+    });
+
+    let assign_action = Action::new(move |test_id: &String| {
+        let test_id = test_id.clone();
+        async move {
+            match retrieval_id.get_untracked() {
+                Some(id) => assign_test(id, test_id).await,
+                None => Err(ServerFnError::new("Invalid retrieval ID")),
+            }
+        }
+    });
+
+    let unassign_action = Action::new(move |(test_id, force): &(String, bool)| {
+        let (test_id, force) = (test_id.clone(), *force);
+        async move {
+            match retrieval_id.get_untracked() {
+                Some(id) => unassign_test(id, test_id, force).await,
+                None => Err(ServerFnError::new("Invalid retrieval ID")),
+            }
+        }
+    });
+
+    let erase_action = Action::new(move |erasure: &Erasure| {
+        let erasure = *erasure;
+        async move {
+            match retrieval_id.get_untracked() {
+                Some(id) => erase_patient(id, erasure).await,
+                None => Err(ServerFnError::new("Invalid retrieval ID")),
+            }
+        }
+    });
+
+    let available_tests = Resource::new(|| (), |_| get_available_tests());
+
+    let resource = Resource::new(
+        move || {
+            (
+                retrieval_id(),
+                create_report_action.version().get(),
+                lock_action.version().get(),
+                assign_action.version().get(),
+                unassign_action.version().get(),
+            )
+        },
+        |(id, ..)| async move {
+            if let Some(id) = id {
+                get_retrieval(id).await
+            } else {
+                Err(ServerFnError::new("Invalid retrieval ID"))
+            }
+        },
+    );
+
+    view! {
+        <StaffBar />
+        <h2>"Abruf"</h2>
+        <ErrorBox>
+            <Suspense fallback=move || {
+                "Loading..."
+            }>
+                {Suspend::new(async move {
+                    let available_tests = available_tests.await?;
+                    resource
+                        .await
+                        .map(|Retrieval { user, tests }| {
+                            let retrieval_id = user.retrieval_id;
+                            let unassigned = available_tests
+                                .into_iter()
+                                .filter(|test| {
+                                    tests.iter().all(|status| status.evaluation.test.id != test.id)
+                                })
+                                .collect::<Vec<_>>();
+                            view! {
+                                <div class="patienten-id">
+                                    <b>"Patienten ID:"</b>
+                                    <pre>{user.id.clone()}</pre>
+                                    <a href=format!("/protokoll?patient={}", user.id)>
+                                        "Zugriffsprotokoll"
+                                    </a>
+                                </div>
+                                {tests
+                                    .into_iter()
+                                    .map(|status| {
+                                        view! {
+                                            <TestStatusElement
+                                                status
+                                                retrieval_id
+                                                create_report=create_report_action
+                                                lock=lock_action
+                                                unassign=unassign_action
+                                            />
+                                        }
+                                    })
+                                    .collect_view()}
+                                <AssignTest tests=unassigned assign=assign_action />
+                                <ErasePatient erase=erase_action />
+                            }
+                        })
+                })}
+            </Suspense>
+            <div>{move || create_report_action.value().get().map(|res| res.map(|_| ()))}</div>
+            <div>{move || lock_action.value().get().map(|res| res.map(|_| ()))}</div>
+            <div>{move || assign_action.value().get()}</div>
+            <div>{move || unassign_action.value().get()}</div>
+            <div>{move || erase_action.value().get()}</div>
+        </ErrorBox>
+    }
+}
+
+#[component]
+fn TestStatusElement(
+    status: TestStatus,
+    retrieval_id: Uuid,
+    create_report: Action<String, ServerFnResult<Evaluation>>,
+    lock: Action<String, ServerFnResult<Submission>>,
+    unassign: Action<(String, bool), ServerFnResult<()>>,
+) -> impl IntoView {
+    let TestStatus {
+        submission,
+        evaluation,
+        reports,
+    } = status;
+    let answered = evaluation.results.len();
+    let progress = match &submission {
+        None => String::from("Nicht begonnen"),
+        Some(Submission {
+            status: SubmissionStatus::InProgress,
+            started_at,
+            ..
+        }) => format!(
+            "{answered} von {} beantwortet, begonnen am {}",
+            evaluation.question_count,
+            format_date(started_at)
+        ),
+        Some(Submission {
+            status: SubmissionStatus::Submitted,
+            submitted_at,
+            ..
+        }) => format!("Abgeschlossen am {}", format_optional_date(submitted_at)),
+        Some(Submission {
+            status: SubmissionStatus::Locked,
+            submitted_at,
+            ..
+        }) => format!(
+            "Gesperrt, abgeschlossen am {}",
+            format_optional_date(submitted_at)
+        ),
+    };
+    let test_id = evaluation.test.id.clone();
+    let answered_before = submission.is_some() || !reports.is_empty();
+    let unassign_button = {
+        let test_id = test_id.clone();
+        view! {
+            <button
+                class="btn"
+                disabled=move || unassign.pending().get()
+                on:click=move |_| {
+                    if !answered_before {
+                        unassign.dispatch((test_id.clone(), false));
+                    } else if window()
+                        .confirm_with_message(
+                            "Zu diesem Test liegen bereits Antworten oder Berichte vor. Test trotzdem entfernen und beides löschen?",
+                        )
+                        .unwrap_or(false)
+                    {
+                        unassign.dispatch((test_id.clone(), true));
+                    }
+                }
+            >
+                "Entfernen"
+            </button>
+        }
+    };
+    let lock_button = submission
+        .filter(|s| s.status != SubmissionStatus::Locked)
+        .map(|_| {
+            let test_id = test_id.clone();
+            view! {
+                <button
+                    class="btn"
+                    disabled=move || lock.pending().get()
+                    on:click=move |_| {
+                        lock.dispatch(test_id.clone());
+                    }
+                >
+                    "Sperren"
+                </button>
+            }
+        });
+    let download = reports.first().map(|report| {
+        let href = format!("/reports/{retrieval_id}/{}.pdf", report.test_id);
+        let created_at = format_date(&report.created_at);
+        view! {
+            <a href=href download>
+                "PDF herunterladen (" {created_at} ")"
+            </a>
+        }
+    });
+
+    view! {
+        <section class="retrieval-test">
+            <h3>{evaluation.test.name}</h3>
+            <p class="progress">{progress}</p>
+            <ScoreTable score=evaluation.score />
+            <div class="actions">
+                <button
+                    class="btn"
+                    disabled=move || create_report.pending().get()
+                    on:click=move |_| {
+                        create_report.dispatch(test_id.clone());
+                    }
+                >
+                    "Bericht erstellen"
+                </button>
+                {lock_button}
+                {unassign_button}
+                {download}
+            </div>
+        </section>
+    }
+}
+
+#[component]
+fn AssignTest(tests: Vec<Test>, assign: Action<String, ServerFnResult<()>>) -> impl IntoView {
+    if tests.is_empty() {
+        return None;
+    }
+    let selected = RwSignal::new(tests[0].id.clone());
+    let options = tests
+        .into_iter()
+        .map(|Test { id, name, .. }| view! { <option value=id>{name}</option> })
+        .collect_view();
+    Some(view! {
+        <section class="assign-test">
+            <label>
+                <span>"Weiteren Test zuweisen"</span>
+                <select on:change=move |ev| selected.set(event_target_value(&ev))>{options}</select>
+            </label>
+            <button
+                class="btn"
+                disabled=move || assign.pending().get()
+                on:click=move |_| {
+                    assign.dispatch(selected.get_untracked());
+                }
+            >
+                "Zuweisen"
+            </button>
+        </section>
+    })
+}
+
+#[component]
+fn ErasePatient(erase: Action<Erasure, ServerFnResult<()>>) -> impl IntoView {
+    let confirm_and_erase = move |erasure: Erasure, message: &str| {
+        if window().confirm_with_message(message).unwrap_or(false) {
+            erase.dispatch(erasure);
+        }
+    };
+
+    view! {
+        <section class="erase-patient">
+            <h3>"Datenschutz"</h3>
+            <div class="actions">
+                <button
+                    class="btn"
+                    disabled=move || erase.pending().get()
+                    on:click=move |_| {
+                        confirm_and_erase(
+                            Erasure::Anonymize,
+                            "Patientendaten und Berichte entfernen? Die Antworten bleiben anonym für Statistiken erhalten.",
+                        )
+                    }
+                >
+                    "Anonymisieren"
+                </button>
+                <button
+                    class="btn"
+                    disabled=move || erase.pending().get()
+                    on:click=move |_| {
+                        confirm_and_erase(
+                            Erasure::Delete,
+                            "Patient mit allen Antworten und Berichten endgültig löschen?",
+                        )
+                    }
+                >
+                    "Endgültig löschen"
+                </button>
+            </div>
+        </section>
+    }
+}
+
+pub(crate) fn format_date(date: &DateTime<Utc>) -> String {
+    date.format("%d.%m.%Y %H:%M").to_string()
+}
+
+fn format_optional_date(date: &Option<DateTime<Utc>>) -> String {
+    date.as_ref().map(format_date).unwrap_or_default()
+}
+
+#[component]
+pub(crate) fn ScoreTable(score: Score) -> impl IntoView {
+    let band_label = |band: Option<Band>| {
+        band.map(|band| {
+            view! { <span class="band" class:clinical=band.clinical>{band.label}</span> }
+        })
+    };
+    let Score {
+        total,
+        band,
+        subscales,
+    } = score;
+
+    view! {
+        <table class="score">
+            <tr>
+                <th>"Skala"</th>
+                <th>"Score"</th>
+                <th>"Einordnung"</th>
+            </tr>
+            {subscales
+                .into_iter()
+                .map(|SubscaleScore { name, sum, band, .. }| {
+                    view! {
+                        <tr>
+                            <td>{name}</td>
+                            <td>{sum}</td>
+                            <td>{band_label(band)}</td>
+                        </tr>
+                    }
+                })
+                .collect_view()}
+            <tr class="total">
+                <td>"Summe"</td>
+                <td>{total}</td>
+                <td>{band_label(band)}</td>
+            </tr>
+        </table>
+    }
+}
@@ -1,13 +1,18 @@
 use crate::app::error_box::ErrorBox;
+use crate::app::link::ValidLink;
 use crate::app::ServerFnResult;
 use crate::types::*;
 use leptos::prelude::*;
 use leptos_router::hooks::use_params_map;
 
 #[server]
-async fn get_assigned_tests(user: String) -> ServerFnResult<Vec<Test>> {
-    crate::db::Db::get()
-        .get_assigned_tests(user)
+async fn get_assigned_tests(token: String) -> ServerFnResult<Vec<TestProgress>> {
+    let db = crate::db::Db::get();
+    let user = db
+        .get_user_by_access_token(token)
+        .await
+        .map_err(ServerFnError::new)?;
+    db.get_assigned_tests(user.id)
         .await
         .map_err(ServerFnError::new)
 }
@@ -15,40 +20,65 @@ async fn get_assigned_tests(user: String) -> ServerFnResult<Vec<Test>> {
 #[component]
 pub(crate) fn Page() -> impl IntoView {
     let params = use_params_map();
-    let user_id = Signal::derive(move || params.read().get("user").and_then(|id| id.parse().ok()));
+    let token = Signal::derive(move || params.read().get("token"));
 
     let resource = Resource::new(
-        move || user_id(),
-        |id| async move {
-            if let Some(id) = id {
-                get_assigned_tests(id).await
+        move || token.get(),
+        |token| async move {
+            if let Some(token) = token {
+                get_assigned_tests(token).await
             } else {
-                Err(ServerFnError::new("User ID needed"))
+                Err(ServerFnError::new("Link needed"))
             }
         },
     );
 
     view! {
-        Welcome...
-        {user_id}
-        <ErrorBox>
-            <div class="user-test-tabs">
-                {move || {
-                    resource
-                        .get()
-                        .transpose()
-                        .map(|list| {
-                            list.into_iter()
-                                .flatten()
-                                .filter_map(move |Test { id, name }| {
-                                    let user = user_id()?;
-                                    let href = format!("/tests/{user}/{id}");
-                                    Some(view! { <a href=href>{name}</a> })
-                                })
-                                .collect_view()
-                        })
-                }}
-            </div>
-        </ErrorBox>
+        <ValidLink>
+            Welcome...
+            <ErrorBox>
+                <div class="user-test-tabs">
+                    {move || {
+                        resource
+                            .get()
+                            .transpose()
+                            .map(|list| {
+                                list.into_iter()
+                                    .flatten()
+                                    .filter_map(move |progress| {
+                                        let token = token()?;
+                                        Some(view! { <TestTab token progress /> })
+                                    })
+                                    .collect_view()
+                            })
+                    }}
+                </div>
+            </ErrorBox>
+        </ValidLink>
+    }
+}
+
+#[component]
+fn TestTab(token: String, progress: TestProgress) -> impl IntoView {
+    let TestProgress {
+        test: Test { id, name, .. },
+        answered,
+        total,
+        submitted,
+    } = progress;
+    let href = format!("/tests/{token}/{id}");
+    let badge = if submitted {
+        String::from("abgeschickt")
+    } else if answered == 0 {
+        String::from("neu")
+    } else {
+        format!("{answered}/{total}")
+    };
+
+    view! {
+        <a href=href class:done=submitted>
+            {name}
+            <span class="badge">{badge}</span>
+        </a>
     }
 }
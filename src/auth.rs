@@ -0,0 +1,91 @@
+use crate::db::Db;
+use crate::types::Staff;
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode};
+use color_eyre::Result;
+use leptos::prelude::*;
+
+const SESSION_COOKIE: &str = "diag_session";
+
+/// Resolves the staff member from the session cookie. Handlers and server functions that take a
+/// [`Staff`] are only reachable while logged in.
+impl<S: Send + Sync> FromRequestParts<S> for Staff {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(token) = session_token(&parts.headers) else {
+            return Err((StatusCode::UNAUTHORIZED, "Not logged in"));
+        };
+        match Db::get().find_session(token).await {
+            Ok(Some(staff)) => Ok(staff),
+            Ok(None) => Err((StatusCode::UNAUTHORIZED, "Session expired")),
+            Err(_) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to look up session",
+            )),
+        }
+    }
+}
+
+/// Fails a server function unless it is called by a logged in staff member.
+pub async fn require_staff() -> Result<Staff, ServerFnError> {
+    leptos_axum::extract::<Staff>()
+        .await
+        .map_err(|_| ServerFnError::new("Anmeldung erforderlich"))
+}
+
+/// The logged in staff member, if any.
+pub async fn current_staff() -> Option<Staff> {
+    leptos_axum::extract::<Staff>().await.ok()
+}
+
+/// Checks the credentials and, if they are valid, starts a session and sets its cookie on the
+/// response.
+#[tracing::instrument(err, skip(password))]
+pub async fn login(username: String, password: String) -> Result<Option<Staff>> {
+    let db = Db::get();
+    let Some(staff) = db.verify_staff_password(username, password).await? else {
+        return Ok(None);
+    };
+    let config = &crate::config::get().auth;
+    let duration = config.session_duration();
+    let token = db.create_session(&staff, duration).await?;
+    set_cookie(&token, duration.as_secs())?;
+    Ok(Some(staff))
+}
+
+/// Ends the current session and clears the cookie.
+#[tracing::instrument(err)]
+pub async fn logout() -> Result<()> {
+    let headers = leptos_axum::extract::<HeaderMap>().await?;
+    if let Some(token) = session_token(&headers) {
+        Db::get().delete_session(token).await?;
+    }
+    set_cookie("", 0)
+}
+
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, token)| token.to_string())
+        .filter(|token| !token.is_empty())
+}
+
+fn set_cookie(token: &str, max_age: u64) -> Result<()> {
+    let secure = if crate::config::get().auth.secure_cookies {
+        "; Secure"
+    } else {
+        ""
+    };
+    let cookie = format!(
+        "{SESSION_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax; Max-Age={max_age}{secure}"
+    );
+    expect_context::<leptos_axum::ResponseOptions>()
+        .append_header(header::SET_COOKIE, HeaderValue::from_str(&cookie)?);
+    Ok(())
+}
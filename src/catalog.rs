@@ -0,0 +1,220 @@
+use crate::types::*;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Loads every `*.json` test definition found in the configured data directory.
+///
+/// Test ids and question ids become SurrealDB record keys, so both have to be unique across
+/// all files.
+#[tracing::instrument(err)]
+pub(crate) async fn load() -> Result<Vec<TestDefinition>> {
+    load_dir(&crate::config::get().data_dir).await
+}
+
+async fn load_dir(dir: &Path) -> Result<Vec<TestDefinition>> {
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .wrap_err_with(|| format!("Failed to read data directory {}", dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut tests = Vec::with_capacity(paths.len());
+    for path in paths {
+        tests.push(load_file(&path).await?);
+    }
+    check_unique_ids(&tests)?;
+    for test in tests.iter() {
+        check_subscales(test)?;
+        check_norms(test)?;
+    }
+    tracing::info!("Loaded {} test definitions", tests.len());
+    Ok(tests)
+}
+
+async fn load_file(path: &Path) -> Result<TestDefinition> {
+    let txt = tokio::fs::read_to_string(path).await?;
+    serde_json::from_str(&txt)
+        .wrap_err_with(|| format!("Invalid test definition in {}", path.display()))
+}
+
+fn check_unique_ids(tests: &[TestDefinition]) -> Result<()> {
+    let mut test_ids = BTreeSet::new();
+    let mut question_ids = BTreeSet::new();
+    for test in tests {
+        if !test_ids.insert(test.id.as_str()) {
+            color_eyre::eyre::bail!("Duplicate test id `{}`", test.id);
+        }
+        for q in test.questions.iter() {
+            if !question_ids.insert(q.id.as_str()) {
+                color_eyre::eyre::bail!("Duplicate question id `{}` in test `{}`", q.id, test.id);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_subscales(test: &TestDefinition) -> Result<()> {
+    for q in test.questions.iter() {
+        for subscale in q.subscales.iter() {
+            if !test.subscales.iter().any(|s| &s.id == subscale) {
+                color_eyre::eyre::bail!(
+                    "Question `{}` refers to unknown subscale `{subscale}` in test `{}`",
+                    q.id,
+                    test.id
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rejects norms for unknown subscales, bands that are empty or overlap, and norms that can
+/// never apply because a general norm of the same scale comes first.
+fn check_norms(test: &TestDefinition) -> Result<()> {
+    for (index, norm) in test.norms.iter().enumerate() {
+        let scale = norm.subscale.as_deref().unwrap_or("total");
+        if let Some(subscale) = &norm.subscale {
+            if !test.subscales.iter().any(|s| &s.id == subscale) {
+                color_eyre::eyre::bail!(
+                    "Norm refers to unknown subscale `{subscale}` in test `{}`",
+                    test.id
+                );
+            }
+        }
+        if norm
+            .bands
+            .iter()
+            .any(|b| b.max.is_some_and(|max| max < b.min))
+        {
+            color_eyre::eyre::bail!("Norm band with max below min in test `{}`", test.id);
+        }
+        let mut bands = norm.bands.iter().collect::<Vec<_>>();
+        bands.sort_by_key(|b| b.min);
+        for pair in bands.windows(2) {
+            if pair[0].max.is_none_or(|max| max >= pair[1].min) {
+                color_eyre::eyre::bail!(
+                    "Norm bands `{}` and `{}` of `{scale}` overlap in test `{}`",
+                    pair[0].label,
+                    pair[1].label,
+                    test.id
+                );
+            }
+        }
+        let shadowed = test.norms[..index].iter().any(|earlier| {
+            earlier.subscale == norm.subscale
+                && earlier.sex.is_none()
+                && earlier.min_age.is_none()
+                && earlier.max_age.is_none()
+        });
+        if shadowed {
+            color_eyre::eyre::bail!(
+                "Norm of `{scale}` follows a norm for all patients and never applies in test `{}`",
+                test.id
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn band(min: usize, max: Option<usize>, label: &str) -> Band {
+        Band {
+            min,
+            max,
+            label: label.to_string(),
+            clinical: false,
+        }
+    }
+
+    fn norm(sex: Option<Sex>, bands: Vec<Band>) -> Norm {
+        Norm {
+            subscale: None,
+            sex,
+            min_age: None,
+            max_age: None,
+            bands,
+        }
+    }
+
+    fn definition(norms: Vec<Norm>) -> TestDefinition {
+        TestDefinition {
+            id: String::from("test"),
+            name: String::from("Test"),
+            version: 1,
+            subscales: vec![Subscale {
+                id: String::from("x"),
+                name: String::from("X"),
+            }],
+            norms,
+            questions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn accepts_adjacent_bands_with_open_end() {
+        let bands = vec![band(0, Some(4), "low"), band(5, None, "high")];
+        assert!(check_norms(&definition(vec![norm(None, bands)])).is_ok());
+    }
+
+    #[test]
+    fn accepts_specific_norms_before_general_ones() {
+        let norms = vec![
+            norm(Some(Sex::Female), vec![band(0, None, "female")]),
+            norm(None, vec![band(0, None, "all")]),
+        ];
+        assert!(check_norms(&definition(norms)).is_ok());
+    }
+
+    #[test]
+    fn rejects_max_below_min() {
+        let bands = vec![band(5, Some(4), "empty")];
+        assert!(check_norms(&definition(vec![norm(None, bands)])).is_err());
+    }
+
+    #[test]
+    fn rejects_overlapping_bands() {
+        let bands = vec![band(5, Some(9), "middle"), band(0, Some(5), "low")];
+        assert!(check_norms(&definition(vec![norm(None, bands)])).is_err());
+    }
+
+    #[test]
+    fn rejects_bands_after_an_open_ended_band() {
+        let bands = vec![band(0, None, "all"), band(10, Some(20), "high")];
+        assert!(check_norms(&definition(vec![norm(None, bands)])).is_err());
+    }
+
+    #[test]
+    fn rejects_norms_after_a_general_norm() {
+        let norms = vec![
+            norm(None, vec![band(0, None, "all")]),
+            norm(Some(Sex::Male), vec![band(0, None, "male")]),
+        ];
+        assert!(check_norms(&definition(norms)).is_err());
+    }
+
+    #[test]
+    fn general_norms_of_other_scales_do_not_shadow() {
+        let mut subscale = norm(None, vec![band(0, None, "x")]);
+        subscale.subscale = Some(String::from("x"));
+        let norms = vec![norm(None, vec![band(0, None, "all")]), subscale];
+        assert!(check_norms(&definition(norms)).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_subscale() {
+        let mut norm = norm(None, vec![band(0, None, "y")]);
+        norm.subscale = Some(String::from("y"));
+        assert!(check_norms(&definition(vec![norm])).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use color_eyre::eyre::{ensure, WrapErr};
+use color_eyre::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// File read when `DIAG_CONFIG` is not set. It is optional, all settings have defaults matching
+/// `pod.yml`.
+const DEFAULT_CONFIG_FILE: &str = "diag.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory holding the questionnaire JSON files.
+    pub data_dir: PathBuf,
+    pub database: DatabaseConfig,
+    pub gotenberg: GotenbergConfig,
+    pub reports: ReportsConfig,
+    pub auth: AuthConfig,
+    pub retention: RetentionConfig,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub endpoint: String,
+    pub username: String,
+    pub password: String,
+    pub namespace: String,
+    pub database: String,
+    pub connect_timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GotenbergConfig {
+    pub url: String,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportsConfig {
+    /// Directory the generated PDF reports are written to.
+    pub dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// How long a staff login stays valid.
+    pub session_hours: u64,
+    /// Sets the `Secure` attribute on the session cookie. Browsers accept secure cookies from
+    /// `localhost`, so this only needs to be disabled when serving plain HTTP to other hosts.
+    pub secure_cookies: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Days without activity after a patient's reports were downloaded until the patient is
+    /// deleted. Nothing is deleted automatically while unset.
+    pub days: Option<u32>,
+    pub check_interval_hours: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("data"),
+            database: DatabaseConfig::default(),
+            gotenberg: GotenbergConfig::default(),
+            reports: ReportsConfig::default(),
+            auth: AuthConfig::default(),
+            retention: RetentionConfig::default(),
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from("localhost:8000"),
+            username: String::from("root"),
+            password: String::from("root"),
+            namespace: String::from("test"),
+            database: String::from("test"),
+            connect_timeout_secs: 10,
+        }
+    }
+}
+
+impl std::fmt::Debug for DatabaseConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatabaseConfig")
+            .field("endpoint", &self.endpoint)
+            .field("username", &self.username)
+            .field("password", &"***")
+            .field("namespace", &self.namespace)
+            .field("database", &self.database)
+            .field("connect_timeout_secs", &self.connect_timeout_secs)
+            .finish()
+    }
+}
+
+impl Default for GotenbergConfig {
+    fn default() -> Self {
+        Self {
+            url: String::from("http://localhost:30000"),
+            timeout_secs: 30,
+        }
+    }
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("reports"),
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            session_hours: 12,
+            secure_cookies: true,
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            days: None,
+            check_interval_hours: 24,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+}
+
+impl GotenbergConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+impl AuthConfig {
+    pub fn session_duration(&self) -> Duration {
+        Duration::from_secs(self.session_hours * 60 * 60)
+    }
+}
+
+impl RetentionConfig {
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_hours * 60 * 60)
+    }
+}
+
+/// Loads, validates and installs the configuration. Must be called once at startup before
+/// [`get`] is used.
+///
+/// Settings are read from the TOML file named by `DIAG_CONFIG` (default `diag.toml`), then
+/// overridden by `DIAG_*` environment variables.
+pub fn load() -> Result<&'static Config> {
+    let mut config = match std::env::var("DIAG_CONFIG") {
+        Ok(path) => read_file(Path::new(&path))?,
+        Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+            read_file(Path::new(DEFAULT_CONFIG_FILE))?
+        }
+        Err(_) => Config::default(),
+    };
+    config.apply_env()?;
+    config.validate()?;
+    tracing::info!(?config, "Configuration loaded");
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// The configuration installed by [`load`].
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("configuration not loaded")
+}
+
+fn read_file(path: &Path) -> Result<Config> {
+    let txt = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&txt).wrap_err_with(|| format!("Invalid config file {}", path.display()))
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn env_secs(name: &str, target: &mut u64) -> Result<()> {
+    if let Some(value) = env_var(name) {
+        *target = value
+            .parse()
+            .wrap_err_with(|| format!("{name} must be a number of seconds, got `{value}`"))?;
+    }
+    Ok(())
+}
+
+fn env_parse<T>(name: &str, target: &mut T) -> Result<()>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    if let Some(value) = env_var(name) {
+        *target = value
+            .parse()
+            .wrap_err_with(|| format!("{name} has an invalid value `{value}`"))?;
+    }
+    Ok(())
+}
+
+impl Config {
+    fn apply_env(&mut self) -> Result<()> {
+        let set = |name: &str, target: &mut String| {
+            if let Some(value) = env_var(name) {
+                *target = value;
+            }
+        };
+        set("DIAG_DB_ENDPOINT", &mut self.database.endpoint);
+        set("DIAG_DB_USERNAME", &mut self.database.username);
+        set("DIAG_DB_PASSWORD", &mut self.database.password);
+        set("DIAG_DB_NAMESPACE", &mut self.database.namespace);
+        set("DIAG_DB_DATABASE", &mut self.database.database);
+        env_secs(
+            "DIAG_DB_CONNECT_TIMEOUT",
+            &mut self.database.connect_timeout_secs,
+        )?;
+        set("DIAG_GOTENBERG_URL", &mut self.gotenberg.url);
+        env_secs("DIAG_GOTENBERG_TIMEOUT", &mut self.gotenberg.timeout_secs)?;
+        if let Some(dir) = env_var("DIAG_REPORT_DIR") {
+            self.reports.dir = PathBuf::from(dir);
+        }
+        if let Some(dir) = env_var("DIAG_DATA_DIR") {
+            self.data_dir = PathBuf::from(dir);
+        }
+        env_parse("DIAG_SESSION_HOURS", &mut self.auth.session_hours)?;
+        env_parse("DIAG_SECURE_COOKIES", &mut self.auth.secure_cookies)?;
+        if env_var("DIAG_RETENTION_DAYS").is_some() {
+            let mut days = 0;
+            env_parse("DIAG_RETENTION_DAYS", &mut days)?;
+            self.retention.days = Some(days);
+        }
+        env_parse(
+            "DIAG_RETENTION_CHECK_INTERVAL_HOURS",
+            &mut self.retention.check_interval_hours,
+        )?;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        let DatabaseConfig {
+            endpoint,
+            username,
+            namespace,
+            database,
+            connect_timeout_secs,
+            ..
+        } = &self.database;
+        ensure!(!endpoint.is_empty(), "database.endpoint must not be empty");
+        ensure!(!username.is_empty(), "database.username must not be empty");
+        ensure!(
+            !namespace.is_empty(),
+            "database.namespace must not be empty"
+        );
+        ensure!(!database.is_empty(), "database.database must not be empty");
+        ensure!(
+            *connect_timeout_secs > 0,
+            "database.connect_timeout_secs must be positive"
+        );
+
+        reqwest::Url::parse(&self.gotenberg.url)
+            .wrap_err_with(|| format!("gotenberg.url `{}` is not a URL", self.gotenberg.url))?;
+        ensure!(
+            self.gotenberg.timeout_secs > 0,
+            "gotenberg.timeout_secs must be positive"
+        );
+        ensure!(
+            self.auth.session_hours > 0,
+            "auth.session_hours must be positive"
+        );
+        ensure!(
+            self.retention.days != Some(0),
+            "retention.days must be positive, leave it unset to keep data indefinitely"
+        );
+        ensure!(
+            self.retention.check_interval_hours > 0,
+            "retention.check_interval_hours must be positive"
+        );
+
+        ensure!(
+            self.data_dir.is_dir(),
+            "data_dir {} is not a directory",
+            self.data_dir.display()
+        );
+        std::fs::create_dir_all(&self.reports.dir).wrap_err_with(|| {
+            format!(
+                "Failed to create reports.dir {}",
+                self.reports.dir.display()
+            )
+        })?;
+        Ok(())
+    }
+}
@@ -1,32 +1,59 @@
 #![cfg(feature = "ssr")]
+mod migrations;
+
 use crate::types::*;
+use chrono::{DateTime, Datelike, NaiveTime, Utc};
 use color_eyre::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::LazyLock;
+use std::time::Duration;
 use uuid::Uuid;
 
 use surrealdb::{
     engine::remote::ws::{Client, Ws},
     opt::auth::Root,
-    RecordId, Surreal,
+    Datetime, RecordId, Surreal,
 };
 
 #[derive(Debug)]
 pub struct Db;
 
+/// How long repeated loads of a retrieval page by the same staff member count as one access.
+const RETRIEVAL_AUDIT_WINDOW: chrono::Duration = chrono::Duration::minutes(30);
+
+/// Converts a datetime read from SurrealDB, which only converts through its inner value.
+fn to_chrono(datetime: Datetime) -> DateTime<Utc> {
+    datetime.into_inner().into()
+}
+
 static DB: LazyLock<Surreal<Client>> = LazyLock::new(Surreal::init);
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct UserRecord {
     pub id: RecordId,
     pub retrieval_id: Uuid,
+    pub access_token: String,
+    #[serde(default)]
+    pub access_expires_at: Option<Datetime>,
+    #[serde(default)]
+    pub expire_when_done: bool,
+    #[serde(default)]
+    pub done_at: Option<Datetime>,
+    #[serde(default)]
+    pub info: PatientInfo,
+    #[serde(default)]
+    pub created_at: Option<Datetime>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TestRecord {
     pub id: RecordId,
     pub name: String,
+    /// The current version, used for new submissions.
+    pub version: u32,
+    pub subscales: Vec<Subscale>,
+    pub norms: Vec<Norm>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -34,6 +61,79 @@ pub struct QuestionRecord {
     pub id: RecordId,
     pub prompt: String,
     pub options: Vec<Opt>,
+    pub key: ScoringKey,
+    pub weight: usize,
+    pub subscales: Vec<String>,
+    /// Index of the question within its test.
+    #[serde(default)]
+    pub position: usize,
+}
+
+/// A test assigned to a user, as loaded for several users at once.
+#[derive(Debug, Deserialize)]
+struct AssignmentRecord {
+    user: String,
+    test: String,
+}
+
+/// A question a user answered, as loaded for several users at once.
+#[derive(Debug, Deserialize)]
+struct UserAnswerRecord {
+    user: String,
+    question_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnswerRecord {
+    pub question_id: String,
+    pub choice: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ReportRecord {
+    pub id: RecordId,
+    pub user: RecordId,
+    pub test: RecordId,
+    pub test_name: String,
+    pub created_at: Datetime,
+    pub score: Score,
+    /// Path of the PDF relative to the report directory.
+    pub file: String,
+    /// When the PDF was last downloaded.
+    #[serde(default)]
+    pub retrieved_at: Option<Datetime>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuditRecord {
+    pub at: Datetime,
+    pub actor: Option<String>,
+    pub action: AuditAction,
+    pub patient: Option<String>,
+    pub details: String,
+}
+
+impl From<AuditRecord> for AuditEntry {
+    fn from(record: AuditRecord) -> Self {
+        AuditEntry {
+            at: to_chrono(record.at),
+            actor: record.actor,
+            action: record.action,
+            patient: record.patient,
+            details: record.details,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SubmissionRecord {
+    pub id: RecordId,
+    pub user: RecordId,
+    pub test: RecordId,
+    pub version: u32,
+    pub status: SubmissionStatus,
+    pub started_at: Datetime,
+    pub submitted_at: Option<Datetime>,
 }
 
 impl TryFrom<UserRecord> for User {
@@ -45,6 +145,12 @@ impl TryFrom<UserRecord> for User {
         Ok(User {
             id,
             retrieval_id: record.retrieval_id,
+            access_token: record.access_token,
+            access_expires_at: record.access_expires_at.map(to_chrono),
+            expire_when_done: record.expire_when_done,
+            done_at: record.done_at.map(to_chrono),
+            info: record.info,
+            created_at: record.created_at.map(to_chrono),
         })
     }
 }
@@ -58,6 +164,9 @@ impl TryFrom<QuestionRecord> for Question {
             id,
             prompt: record.prompt,
             options: record.options,
+            key: record.key,
+            weight: record.weight,
+            subscales: record.subscales,
         })
     }
 }
@@ -65,14 +174,24 @@ impl TryFrom<QuestionRecord> for Question {
 impl Db {
     #[tracing::instrument(err)]
     pub async fn connect() -> Result<Self> {
-        DB.connect::<Ws>("localhost:8000").await?;
+        let config = &crate::config::get().database;
+        tokio::time::timeout(
+            config.connect_timeout(),
+            DB.connect::<Ws>(config.endpoint.as_str()),
+        )
+        .await
+        .map_err(|_| {
+            color_eyre::eyre::eyre!("Timed out connecting to SurrealDB at {}", config.endpoint)
+        })??;
         DB.signin(Root {
-            username: "root",
-            password: "root",
+            username: &config.username,
+            password: &config.password,
         })
         .await?;
 
-        DB.use_ns("test").use_db("test").await?;
+        DB.use_ns(&config.namespace)
+            .use_db(&config.database)
+            .await?;
 
         let db = Self;
         db.setup().await?;
@@ -81,72 +200,407 @@ impl Db {
 
     #[tracing::instrument(err)]
     async fn setup(&self) -> Result<()> {
-        DB.query(
-            r#"
-            BEGIN;
-            DEFINE TABLE IF NOT EXISTS user;
-            DEFINE FIELD IF NOT EXISTS retrieval_id ON TABLE user TYPE uuid READONLY VALUE rand::uuid();
-            DEFINE TABLE IF NOT EXISTS says TYPE RELATION IN user OUT question ENFORCED;
-            DEFINE TABLE OVERWRITE test; DELETE test;
-            DEFINE TABLE OVERWRITE question; DELETE question;
-            COMMIT;
-        "#,
-        )
-        .await?;
-
-        let txt = include_str!("../data/aq.json");
-        let questions: Vec<Question> = serde_json::from_str(&txt)?;
+        migrations::run().await?;
+        for test in crate::catalog::load().await? {
+            self.sync_test(test).await?;
+        }
+        Ok(())
+    }
 
+    /// Upserts a test definition and its questions by their stable ids.
+    ///
+    /// Questions dropped from the definition are unlinked from the test but kept, so answers
+    /// given to them stay intact.
+    #[tracing::instrument(err, skip(test), fields(test = %test.id))]
+    async fn sync_test(&self, test: TestDefinition) -> Result<()> {
+        self.freeze_test_version(&test).await?;
+        let TestDefinition {
+            id,
+            name,
+            version,
+            subscales,
+            norms,
+            questions,
+        } = test;
+        let test_id = RecordId::from_table_key("test", id);
         let _: Option<TestRecord> = DB
-            .create("test")
+            .upsert(test_id.clone())
             .content(TestRecord {
-                id: RecordId::from_table_key("test", "aq"),
-                name: String::from("AQ"),
+                id: test_id.clone(),
+                name,
+                version,
+                subscales,
+                norms,
             })
             .await?;
-        let records: Vec<QuestionRecord> = DB.insert("question").content(questions).await?;
-        for r in records.into_iter() {
-            DB.query("RELATE test:aq -> contains -> $ID;")
-                .bind(("ID", r.id))
+
+        let mut question_ids = Vec::with_capacity(questions.len());
+        for (position, q) in questions.into_iter().enumerate() {
+            let question_id = RecordId::from_table_key("question", q.id);
+            let _: Option<QuestionRecord> = DB
+                .upsert(question_id.clone())
+                .content(QuestionRecord {
+                    id: question_id.clone(),
+                    prompt: q.prompt,
+                    options: q.options,
+                    key: q.key,
+                    weight: q.weight,
+                    subscales: q.subscales,
+                    position,
+                })
                 .await?;
+            question_ids.push(question_id);
         }
+
+        DB.query(
+            r#"
+            BEGIN;
+            DELETE contains WHERE in = $TEST AND out NOTINSIDE $QUESTIONS;
+            LET $LINKED = SELECT VALUE out FROM contains WHERE in = $TEST;
+            FOR $QUESTION IN $QUESTIONS {
+                IF $QUESTION NOTINSIDE $LINKED {
+                    RELATE $TEST -> contains -> $QUESTION;
+                };
+            };
+            COMMIT;
+            "#,
+        )
+        .bind(("TEST", test_id))
+        .bind(("QUESTIONS", question_ids))
+        .await?
+        .check()?;
         Ok(())
     }
 
+    /// Stores a snapshot of a test version, or verifies that an existing snapshot still matches
+    /// the definition.
+    async fn freeze_test_version(&self, test: &TestDefinition) -> Result<()> {
+        let frozen = self
+            .find_test_version(test.id.clone(), test.version)
+            .await?;
+        match frozen {
+            Some(frozen) if &frozen != test => color_eyre::eyre::bail!(
+                "Test `{}` version {} differs from the stored version. Increase `version` when changing a test.",
+                test.id,
+                test.version
+            ),
+            Some(_) => Ok(()),
+            None => {
+                tracing::info!("Storing version {} of test `{}`", test.version, test.id);
+                DB.query(
+                    r#"
+                    CREATE type::thing("test_version", [$TEST, $VERSION]) SET
+                        test = type::thing("test", $TEST),
+                        version = $VERSION,
+                        definition = $DEFINITION,
+                        created_at = time::now();
+                    "#,
+                )
+                .bind(("TEST", test.id.clone()))
+                .bind(("VERSION", test.version))
+                .bind(("DEFINITION", test.clone()))
+                .await?
+                .check()?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn find_test_version(
+        &self,
+        test_id: String,
+        version: u32,
+    ) -> Result<Option<TestDefinition>> {
+        Ok(DB
+            .query(
+                r#"SELECT VALUE definition FROM ONLY type::thing("test_version", [$TEST, $VERSION])"#,
+            )
+            .bind(("TEST", test_id))
+            .bind(("VERSION", version))
+            .await?
+            .take(0)?)
+    }
+
     pub fn get() -> Self {
         Self
     }
 
+    /// Creates a staff account, or replaces the password of an existing one.
+    #[tracing::instrument(err, skip(password))]
+    pub async fn set_staff_password(&self, username: String, password: String) -> Result<()> {
+        DB.query(
+            r#"
+            UPSERT type::thing("staff", $USERNAME) SET
+                username = $USERNAME,
+                password_hash = crypto::argon2::generate($PASSWORD);
+            "#,
+        )
+        .bind(("USERNAME", username))
+        .bind(("PASSWORD", password))
+        .await?
+        .check()?;
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(password))]
+    pub async fn verify_staff_password(
+        &self,
+        username: String,
+        password: String,
+    ) -> Result<Option<Staff>> {
+        Ok(DB
+            .query(
+                r#"
+                SELECT username FROM type::thing("staff", $USERNAME)
+                    WHERE crypto::argon2::compare(password_hash, $PASSWORD);
+                "#,
+            )
+            .bind(("USERNAME", username))
+            .bind(("PASSWORD", password))
+            .await?
+            .take::<Vec<Staff>>(0)?
+            .into_iter()
+            .next())
+    }
+
+    /// Starts a session for the staff member and returns its token. Expired sessions are
+    /// removed on the way.
+    #[tracing::instrument(err)]
+    pub async fn create_session(&self, staff: &Staff, duration: Duration) -> Result<String> {
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let expires_at = chrono::Utc::now() + duration;
+        DB.query(
+            r#"
+            BEGIN;
+            DELETE session WHERE expires_at < time::now();
+            CREATE type::thing("session", $TOKEN) SET
+                staff = type::thing("staff", $USERNAME),
+                expires_at = $EXPIRES_AT;
+            COMMIT;
+            "#,
+        )
+        .bind(("TOKEN", token.clone()))
+        .bind(("USERNAME", staff.username.clone()))
+        .bind(("EXPIRES_AT", Datetime::from(expires_at)))
+        .await?
+        .check()?;
+        Ok(token)
+    }
+
+    #[tracing::instrument(err, skip_all)]
+    pub async fn find_session(&self, token: String) -> Result<Option<Staff>> {
+        Ok(DB
+            .query(
+                r#"
+                SELECT staff.username AS username FROM type::thing("session", $TOKEN)
+                    WHERE expires_at > time::now();
+                "#,
+            )
+            .bind(("TOKEN", token))
+            .await?
+            .take::<Vec<Staff>>(0)?
+            .into_iter()
+            .next())
+    }
+
+    #[tracing::instrument(err, skip_all)]
+    pub async fn delete_session(&self, token: String) -> Result<()> {
+        DB.query(r#"DELETE type::thing("session", $TOKEN)"#)
+            .bind(("TOKEN", token))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
     #[tracing::instrument(err)]
-    pub async fn create_user(&self, tests: BTreeSet<String>) -> Result<User> {
+    pub async fn create_user(&self, new_user: NewUser, actor: &Staff) -> Result<User> {
+        let NewUser {
+            tests,
+            valid_days,
+            expire_when_done,
+            info,
+        } = new_user;
+        let expires_at = valid_days
+            .map(|days| Datetime::from(chrono::Utc::now() + chrono::Duration::days(days.into())));
         let user: Option<UserRecord> = DB
             .query(
                 r#"
-        LET $USER = CREATE user;
+        LET $USER = CREATE user SET
+            access_expires_at = $EXPIRES_AT,
+            expire_when_done = $EXPIRE_WHEN_DONE,
+            info = $INFO;
         RELATE $USER -> assigned -> (select * from test where meta::id(id) in $TESTS);
         RETURN $USER
         "#,
             )
-            .bind(("TESTS", tests))
+            .bind(("TESTS", tests.clone()))
+            .bind(("EXPIRES_AT", expires_at))
+            .bind(("EXPIRE_WHEN_DONE", expire_when_done))
+            .bind(("INFO", info))
             .await?
             .take(2)?;
-        Ok(user.unwrap().try_into()?)
+        let user = User::try_from(user.unwrap())?;
+        let tests = tests.into_iter().collect::<Vec<_>>().join(", ");
+        self.audit(
+            Some(actor.username.clone()),
+            AuditAction::PatientCreated,
+            Some(user.id.clone()),
+            format!("Tests: {tests}"),
+        )
+        .await?;
+        Ok(user)
+    }
+
+    #[tracing::instrument(err)]
+    pub async fn get_user(&self, user_id: String) -> Result<User> {
+        let user: Option<UserRecord> = DB.select(("user", user_id.as_str())).await?;
+        let Some(user) = user else {
+            color_eyre::eyre::bail!("Unknown user `{user_id}`");
+        };
+        user.try_into()
+    }
+
+    #[tracing::instrument(err)]
+    pub async fn get_test(&self, test_id: String) -> Result<Test> {
+        let test: Option<TestRecord> = DB.select(("test", test_id.as_str())).await?;
+        let Some(test) = test else {
+            color_eyre::eyre::bail!("Unknown test `{test_id}`");
+        };
+        Ok(test.into())
+    }
+
+    /// The frozen wording and scoring of one version of a test.
+    #[tracing::instrument(err)]
+    pub async fn get_test_version(&self, test_id: String, version: u32) -> Result<Questionnaire> {
+        let Some(definition) = self.find_test_version(test_id.clone(), version).await? else {
+            color_eyre::eyre::bail!("Unknown version {version} of test `{test_id}`");
+        };
+        Ok(definition.into())
+    }
+
+    /// The version of a test a user answers: the one their submission was started with, or the
+    /// current one for a new submission.
+    #[tracing::instrument(err)]
+    pub async fn get_questions(&self, user_id: String, test_id: String) -> Result<Questionnaire> {
+        let version = match self.get_submission(user_id, test_id.clone()).await? {
+            Some(submission) => submission.version,
+            None => self.get_test(test_id.clone()).await?.version,
+        };
+        self.get_test_version(test_id, version).await
     }
 
     #[tracing::instrument(err)]
-    pub async fn get_questions(&self) -> Result<Vec<Question>> {
-        let questions: Vec<QuestionRecord> = DB
-            .query("SELECT * from test:aq -> contains -> question")
+    pub async fn get_submission(
+        &self,
+        user_id: String,
+        test_id: String,
+    ) -> Result<Option<Submission>> {
+        let record: Option<SubmissionRecord> = DB
+            .query(r#"SELECT * FROM ONLY type::thing("submission", [$USER, $TEST])"#)
+            .bind(("USER", user_id))
+            .bind(("TEST", test_id))
             .await?
             .take(0)?;
+        Ok(record.map(Submission::from))
+    }
 
-        questions.into_iter().map(|q| q.try_into()).collect()
+    #[tracing::instrument(err)]
+    pub async fn list_submissions(&self, user_id: String) -> Result<Vec<Submission>> {
+        Ok(DB
+            .query(r#"SELECT * FROM submission WHERE user = type::thing("user", $USER)"#)
+            .bind(("USER", user_id))
+            .await?
+            .take::<Vec<SubmissionRecord>>(0)?
+            .into_iter()
+            .map(Submission::from)
+            .collect())
     }
 
+    /// Closes a submission for good; later calls to [`Db::submit_test`] are rejected.
     #[tracing::instrument(err)]
-    pub async fn submit_test(&self, data: Data) -> Result<()> {
+    pub async fn lock_submission(&self, user_id: String, test_id: String) -> Result<Submission> {
+        let record: Option<SubmissionRecord> = DB
+            .query(
+                r#"
+                UPDATE ONLY type::thing("submission", [$USER, $TEST])
+                    SET status = "locked", submitted_at = submitted_at OR time::now()
+                    RETURN AFTER;
+                "#,
+            )
+            .bind(("USER", user_id))
+            .bind(("TEST", test_id.clone()))
+            .await?
+            .take(0)?;
+        let Some(record) = record else {
+            color_eyre::eyre::bail!("There are no answers for test `{test_id}` yet");
+        };
+        Ok(record.into())
+    }
+
+    /// Saves answers and, with `finish`, marks the submission as submitted once every question
+    /// is answered. Submitted and locked tests can no longer be changed.
+    #[tracing::instrument(err)]
+    pub async fn submit_test(&self, data: Data, finish: bool) -> Result<()> {
         tracing::info!("Submitting test data");
-        let user_id = data.user.id;
+        let user = self.get_user_by_access_token(data.user.token).await?;
+        let user_id = user.id.clone();
+        let TestVersion {
+            id: test_id,
+            version,
+        } = data.test;
+        self.ensure_assigned(user_id.clone(), test_id.clone())
+            .await?;
+        let questionnaire = self.get_test_version(test_id.clone(), version).await?;
+        for (q_id, choice) in data.q.iter() {
+            let Some(question) = questionnaire.questions.iter().find(|q| &q.id == q_id) else {
+                color_eyre::eyre::bail!("Question `{q_id}` is not part of test `{test_id}`");
+            };
+            if *choice >= question.options.len() {
+                color_eyre::eyre::bail!("Invalid choice {choice} for question `{q_id}`");
+            }
+        }
+
+        // Creates the submission unless it exists, so concurrent autosaves of a fresh test
+        // cannot collide.
+        let submission: Option<SubmissionRecord> = DB
+            .query(
+                r#"
+                UPSERT ONLY type::thing("submission", [$USER, $TEST]) SET
+                    user = type::thing("user", $USER),
+                    test = type::thing("test", $TEST),
+                    version = version ?? $VERSION
+                    RETURN AFTER;
+                "#,
+            )
+            .bind(("USER", user_id.clone()))
+            .bind(("TEST", test_id.clone()))
+            .bind(("VERSION", version))
+            .await?
+            .take(0)?;
+        let Some(submission) = submission.map(Submission::from) else {
+            color_eyre::eyre::bail!("Failed to start a submission for test `{test_id}`");
+        };
+        match submission {
+            Submission {
+                status: SubmissionStatus::Locked,
+                ..
+            } => color_eyre::eyre::bail!(
+                "Test `{test_id}` has been locked and can no longer be changed"
+            ),
+            Submission {
+                status: SubmissionStatus::Submitted,
+                ..
+            } => color_eyre::eyre::bail!(
+                "Test `{test_id}` has been submitted and can no longer be changed"
+            ),
+            Submission {
+                version: started, ..
+            } if started != version => color_eyre::eyre::bail!(
+                "Answers were given to version {version} of test `{test_id}`, but the submission was started with version {started}"
+            ),
+            _ => {}
+        }
+
         for (q_id, choice) in data.q.into_iter() {
             DB.query(
                 r#"
@@ -154,37 +608,744 @@ impl Db {
                     LET $UID = type::thing("user", $USER_ID);
                     LET $QID = type::thing("question", $QUESTION_ID);
                     DELETE FROM says where in = $UID AND out = $QID;
-                    RELATE ONLY $UID -> says -> $QID set choice = $CHOICE;
+                    RELATE ONLY $UID -> says -> $QID set choice = $CHOICE, version = $VERSION;
                     COMMIT;
                 "#,
             )
             .bind(("USER_ID", user_id.clone()))
             .bind(("QUESTION_ID", q_id))
             .bind(("CHOICE", choice))
+            .bind(("VERSION", version))
             .await?
             .check()?;
         }
         tracing::info!("Test submission saved!");
+
+        if finish {
+            let evaluation = self.score_test(user_id.clone(), test_id.clone()).await?;
+            if !evaluation.is_complete() {
+                color_eyre::eyre::bail!(
+                    "Only {} of {} questions have been answered",
+                    evaluation.results.len(),
+                    evaluation.question_count
+                );
+            }
+            DB.query(
+                r#"
+                UPDATE type::thing("submission", [$USER, $TEST])
+                    SET status = "submitted", submitted_at = time::now()
+                    WHERE status = "in_progress";
+                "#,
+            )
+            .bind(("USER", user_id.clone()))
+            .bind(("TEST", test_id))
+            .await?
+            .check()?;
+            tracing::info!("Test submitted");
+
+            if user.expire_when_done
+                && self
+                    .get_assigned_tests(user_id.clone())
+                    .await?
+                    .iter()
+                    .all(|t| t.submitted)
+            {
+                tracing::info!("All tests submitted, expiring the patient link");
+                DB.query(r#"UPDATE type::thing("user", $USER) SET done_at = time::now()"#)
+                    .bind(("USER", user_id))
+                    .await?
+                    .check()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Scores the answers of a user and stores the PDF report.
+    #[tracing::instrument(err)]
+    pub async fn evaluate_test(
+        &self,
+        user_id: String,
+        test_id: String,
+        actor: &Staff,
+    ) -> Result<Evaluation> {
+        let evaluation = self.score_test(user_id.clone(), test_id).await?;
+        let user = self.get_user(user_id.clone()).await?;
+        let pdf = crate::report::generate_pdf(&user, &evaluation).await?;
+        self.save_report(user_id.clone(), &evaluation, pdf).await?;
+        self.audit(
+            Some(actor.username.clone()),
+            AuditAction::ReportGenerated,
+            Some(user_id),
+            format!("Bericht {} erstellt", evaluation.test.name),
+        )
+        .await?;
+        Ok(evaluation)
+    }
+
+    /// Scores the answers of a user without generating a report.
+    #[tracing::instrument(err)]
+    pub async fn score_test(&self, user_id: String, test_id: String) -> Result<Evaluation> {
+        let demographics = self
+            .get_user(user_id.clone())
+            .await?
+            .info
+            .demographics(chrono::Utc::now().year());
+        let Questionnaire { test, questions } =
+            self.get_questions(user_id.clone(), test_id).await?;
+        let answers = self.answers_for(user_id, &questions).await?;
+
+        let results = questions
+            .iter()
+            .filter_map(|q| TestResultRecord::new(q, *answers.get(&q.id)?))
+            .collect::<Vec<_>>();
+        Ok(Evaluation {
+            score: Score::new(&test, &results, &demographics),
+            question_count: questions.len(),
+            test,
+            results,
+        })
+    }
+
+    /// The questions of the version the user is answering, each with the choice saved so far.
+    #[tracing::instrument(err)]
+    pub async fn get_answered_questions(
+        &self,
+        user_id: String,
+        test_id: String,
+    ) -> Result<AnsweredQuestionnaire> {
+        self.ensure_assigned(user_id.clone(), test_id.clone())
+            .await?;
+        let submitted = self
+            .get_submission(user_id.clone(), test_id.clone())
+            .await?
+            .is_some_and(|s| s.is_finished());
+        let Questionnaire { test, questions } =
+            self.get_questions(user_id.clone(), test_id).await?;
+        let answers = self.answers_for(user_id, &questions).await?;
+        let questions = questions
+            .into_iter()
+            .map(|question| AnsweredQuestion {
+                choice: answers.get(&question.id).copied(),
+                question,
+            })
+            .collect();
+        Ok(AnsweredQuestionnaire {
+            test,
+            questions,
+            submitted,
+        })
+    }
+
+    async fn answers_for(
+        &self,
+        user_id: String,
+        questions: &[Question],
+    ) -> Result<BTreeMap<String, usize>> {
+        let question_ids = questions
+            .iter()
+            .map(|q| RecordId::from_table_key("question", q.id.clone()))
+            .collect::<Vec<_>>();
+        Ok(DB
+            .query(
+                r#"
+                SELECT meta::id(out) AS question_id, choice
+                    FROM says WHERE meta::id(in) = $USER_ID AND out INSIDE $QUESTIONS;
+                "#,
+            )
+            .bind(("USER_ID", user_id))
+            .bind(("QUESTIONS", question_ids))
+            .await?
+            .take::<Vec<AnswerRecord>>(0)?
+            .into_iter()
+            .map(|a| (a.question_id, a.choice))
+            .collect())
+    }
+
+    #[tracing::instrument(err, skip(evaluation, pdf))]
+    async fn save_report(
+        &self,
+        user_id: String,
+        evaluation: &Evaluation,
+        pdf: Vec<u8>,
+    ) -> Result<Report> {
+        let key = Uuid::new_v4().to_string();
+        let file = format!("{user_id}/{}-{key}.pdf", evaluation.test.id);
+        crate::report::write_report(&file, &pdf).await?;
+
+        let record: Option<ReportRecord> = DB
+            .create("report")
+            .content(ReportRecord {
+                id: RecordId::from_table_key("report", key),
+                user: RecordId::from_table_key("user", user_id),
+                test: RecordId::from_table_key("test", evaluation.test.id.clone()),
+                test_name: evaluation.test.name.clone(),
+                created_at: Datetime::from(chrono::Utc::now()),
+                score: evaluation.score.clone(),
+                file,
+                retrieved_at: None,
+            })
+            .await?;
+        let Some(record) = record else {
+            color_eyre::eyre::bail!("Failed to store report");
+        };
+        record.try_into()
+    }
+
+    /// Lists the stored reports of a user, newest first.
+    #[tracing::instrument(err)]
+    pub async fn list_reports(&self, user_id: String) -> Result<Vec<Report>> {
+        DB
+            .query(
+                r#"
+                SELECT * FROM report WHERE user = type::thing("user", $USER) ORDER BY created_at DESC;
+                "#,
+            )
+            .bind(("USER", user_id))
+            .await?
+            .take::<Vec<ReportRecord>>(0)?
+            .into_iter()
+            .map(Report::try_from)
+            .collect()
+    }
+
+    /// Reads the newest stored report of a user for a test.
+    #[tracing::instrument(err)]
+    pub async fn get_latest_report_pdf(
+        &self,
+        user_id: String,
+        test_id: String,
+    ) -> Result<Option<(Report, Vec<u8>)>> {
+        let record = DB
+            .query(
+                r#"
+                SELECT * FROM report
+                    WHERE user = type::thing("user", $USER) AND test = type::thing("test", $TEST)
+                    ORDER BY created_at DESC LIMIT 1;
+                "#,
+            )
+            .bind(("USER", user_id))
+            .bind(("TEST", test_id))
+            .await?
+            .take::<Vec<ReportRecord>>(0)?
+            .into_iter()
+            .next();
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        let pdf = crate::report::read_report(&record.file).await?;
+        Ok(Some((record.try_into()?, pdf)))
+    }
+
+    #[tracing::instrument(err)]
+    pub async fn find_user_by_retrieval_id(&self, retrieval_id: Uuid) -> Result<Option<User>> {
+        DB.query(
+            "SELECT * FROM user WHERE retrieval_id = <uuid> $RETRIEVAL_ID AND anonymized_at = NONE",
+        )
+        .bind(("RETRIEVAL_ID", retrieval_id.to_string()))
+        .await?
+        .take::<Vec<UserRecord>>(0)?
+        .into_iter()
+        .next()
+        .map(User::try_from)
+        .transpose()
+    }
+
+    /// Resolves the access token of a patient link. Expired tokens resolve to nobody.
+    #[tracing::instrument(err, skip_all)]
+    pub async fn find_user_by_access_token(&self, token: String) -> Result<Option<User>> {
+        DB.query(
+            r#"
+            SELECT * FROM user WHERE access_token = $TOKEN AND anonymized_at = NONE
+                AND (access_expires_at = NONE OR access_expires_at > time::now())
+                AND (expire_when_done = false OR done_at = NONE);
+            "#,
+        )
+        .bind(("TOKEN", token))
+        .await?
+        .take::<Vec<UserRecord>>(0)?
+        .into_iter()
+        .next()
+        .map(User::try_from)
+        .transpose()
+    }
+
+    #[tracing::instrument(err, skip_all)]
+    pub async fn get_link_status(&self, token: String) -> Result<LinkStatus> {
+        let user = DB
+            .query("SELECT * FROM user WHERE access_token = $TOKEN")
+            .bind(("TOKEN", token))
+            .await?
+            .take::<Vec<UserRecord>>(0)?
+            .into_iter()
+            .next()
+            .map(User::try_from)
+            .transpose()?;
+        Ok(match user {
+            None => LinkStatus::Unknown,
+            Some(user) if user.link_expired(chrono::Utc::now()) => LinkStatus::Expired,
+            Some(_) => LinkStatus::Valid,
+        })
+    }
+
+    #[tracing::instrument(err, skip_all)]
+    pub async fn get_user_by_access_token(&self, token: String) -> Result<User> {
+        let Some(user) = self.find_user_by_access_token(token).await? else {
+            color_eyre::eyre::bail!("Unknown or expired link");
+        };
+        Ok(user)
+    }
+
+    #[tracing::instrument(err)]
+    pub async fn get_user_by_retrieval_id(&self, retrieval_id: Uuid) -> Result<User> {
+        let Some(user) = self.find_user_by_retrieval_id(retrieval_id).await? else {
+            color_eyre::eyre::bail!("Unknown retrieval ID");
+        };
+        Ok(user)
+    }
+
+    /// Collects everything a clinician sees for a patient: the scored state of every assigned
+    /// test together with the reports stored for it.
+    ///
+    /// Every access is audited, but repeated loads by the same staff member within
+    /// [`RETRIEVAL_AUDIT_WINDOW`], e.g. after each action on the page, count as one.
+    #[tracing::instrument(err)]
+    pub async fn get_retrieval(&self, retrieval_id: Uuid, actor: &Staff) -> Result<Retrieval> {
+        let user = self.get_user_by_retrieval_id(retrieval_id).await?;
+        DB.query(
+            r#"
+            IF (SELECT VALUE id FROM audit
+                WHERE actor = $ACTOR AND action = $ACTION AND patient = $PATIENT AND at > $SINCE
+                LIMIT 1) = [] {
+                CREATE audit SET actor = $ACTOR, action = $ACTION, patient = $PATIENT, details = "";
+            };
+            "#,
+        )
+        .bind(("ACTOR", actor.username.clone()))
+        .bind(("ACTION", AuditAction::RetrievalOpened))
+        .bind(("PATIENT", user.id.clone()))
+        .bind((
+            "SINCE",
+            Datetime::from(chrono::Utc::now() - RETRIEVAL_AUDIT_WINDOW),
+        ))
+        .await?
+        .check()?;
+        let mut reports = self.list_reports(user.id.clone()).await?;
+        let mut submissions = self.list_submissions(user.id.clone()).await?;
+        let mut tests = Vec::new();
+        for test in self.assigned_tests(user.id.clone()).await? {
+            let evaluation = self.score_test(user.id.clone(), test.id).await?;
+            let (test_reports, rest) = reports
+                .into_iter()
+                .partition(|r| r.test_id == evaluation.test.id);
+            reports = rest;
+            let submission = submissions
+                .iter()
+                .position(|s| s.test_id == evaluation.test.id)
+                .map(|index| submissions.swap_remove(index));
+            tests.push(TestStatus {
+                submission,
+                evaluation,
+                reports: test_reports,
+            });
+        }
+        Ok(Retrieval { user, tests })
+    }
+
+    /// Assigns another test to an existing user. Assigning a test twice has no effect.
+    ///
+    /// A link that expired because every test was submitted becomes valid again, so the patient
+    /// can answer the new test.
+    #[tracing::instrument(err)]
+    pub async fn assign_test(&self, user_id: String, test_id: String) -> Result<()> {
+        self.get_user(user_id.clone()).await?;
+        self.get_test(test_id.clone()).await?;
+        DB.query(
+            r#"
+            LET $USER = type::thing("user", $USER_ID);
+            LET $TEST = type::thing("test", $TEST_ID);
+            IF (SELECT VALUE id FROM assigned WHERE in = $USER AND out = $TEST) = [] {
+                RELATE $USER -> assigned -> $TEST;
+                UPDATE $USER SET done_at = NONE;
+            };
+            "#,
+        )
+        .bind(("USER_ID", user_id))
+        .bind(("TEST_ID", test_id))
+        .await?
+        .check()?;
+        Ok(())
+    }
+
+    /// Removes a test from a user. Once the user has started answering it or a report exists,
+    /// the test is only removed with `force`, which deletes the answers, the submission and the
+    /// reports as well.
+    #[tracing::instrument(err)]
+    pub async fn unassign_test(&self, user_id: String, test_id: String, force: bool) -> Result<()> {
+        let submission = self
+            .get_submission(user_id.clone(), test_id.clone())
+            .await?;
+        let files: Vec<String> = DB
+            .query(
+                r#"
+                SELECT VALUE file FROM report
+                    WHERE user = type::thing("user", $USER) AND test = type::thing("test", $TEST);
+                "#,
+            )
+            .bind(("USER", user_id.clone()))
+            .bind(("TEST", test_id.clone()))
+            .await?
+            .take(0)?;
+        if (submission.is_some() || !files.is_empty()) && !force {
+            color_eyre::eyre::bail!(
+                "Test `{test_id}` has already been answered, removing it would delete the answers and reports"
+            );
+        }
+        let questions = match submission {
+            Some(submission) => self
+                .get_test_version(test_id.clone(), submission.version)
+                .await?
+                .questions
+                .into_iter()
+                .map(|q| RecordId::from_table_key("question", q.id))
+                .collect(),
+            None => Vec::new(),
+        };
+        DB.query(
+            r#"
+            BEGIN;
+            LET $USER = type::thing("user", $USER_ID);
+            LET $TEST = type::thing("test", $TEST_ID);
+            DELETE assigned WHERE in = $USER AND out = $TEST;
+            DELETE says WHERE in = $USER AND out INSIDE $QUESTIONS;
+            DELETE type::thing("submission", [$USER_ID, $TEST_ID]);
+            DELETE report WHERE user = $USER AND test = $TEST;
+            COMMIT;
+            "#,
+        )
+        .bind(("USER_ID", user_id))
+        .bind(("TEST_ID", test_id))
+        .bind(("QUESTIONS", questions))
+        .await?
+        .check()?;
+        // The test is already removed, so a file left on disk is only logged.
+        for file in files {
+            if let Err(err) = crate::report::delete_report(&file).await {
+                tracing::error!("Failed to delete report file {file}: {err:?}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Erases a patient in one transaction and then removes their report files. Files that cannot
+    /// be deleted are logged and counted, since the patient is already gone at that point.
+    #[tracing::instrument(err)]
+    pub async fn erase_user(&self, user_id: String, erasure: Erasure) -> Result<ErasedData> {
+        let mut response = DB
+            .query(
+                r#"
+                LET $USER = type::thing("user", $USER_ID);
+                SELECT VALUE file FROM report WHERE user = $USER;
+                RETURN count(SELECT VALUE id FROM says WHERE in = $USER);
+                "#,
+            )
+            .bind(("USER_ID", user_id.clone()))
+            .await?;
+        let files: Vec<String> = response.take(1)?;
+        let answers: Option<usize> = response.take(2)?;
+        let query = match erasure {
+            Erasure::Delete => {
+                r#"
+                BEGIN;
+                LET $USER = type::thing("user", $USER_ID);
+                DELETE says WHERE in = $USER;
+                DELETE assigned WHERE in = $USER;
+                DELETE submission WHERE user = $USER;
+                DELETE report WHERE user = $USER;
+                DELETE $USER;
+                COMMIT;
+                "#
+            }
+            Erasure::Anonymize => {
+                r#"
+                BEGIN;
+                LET $USER = type::thing("user", $USER_ID);
+                DELETE report WHERE user = $USER;
+                UPDATE $USER SET
+                    info = {},
+                    access_token = rand::string(48),
+                    access_expires_at = time::now(),
+                    anonymized_at = time::now();
+                COMMIT;
+                "#
+            }
+        };
+        DB.query(query).bind(("USER_ID", user_id)).await?.check()?;
+        tracing::info!(count = files.len(), "Patient erased, removing report files");
+        let mut erased = ErasedData {
+            answers: match erasure {
+                Erasure::Delete => answers.unwrap_or_default(),
+                Erasure::Anonymize => 0,
+            },
+            reports: files.len(),
+            undeleted_files: 0,
+        };
+        for file in files {
+            if let Err(err) = crate::report::delete_report(&file).await {
+                tracing::error!("Failed to delete report file {file}: {err:?}");
+                erased.undeleted_files += 1;
+            }
+        }
+        Ok(erased)
+    }
+
+    /// Users who had a report downloaded and have seen no activity since `cutoff`: no report
+    /// created or downloaded, no test assigned, started, answered or submitted.
+    #[tracing::instrument(err)]
+    pub async fn find_expired_users(&self, cutoff: DateTime<Utc>) -> Result<Vec<String>> {
+        Ok(DB
+            .query(
+                r#"
+                SELECT VALUE meta::id(id) FROM user
+                    WHERE anonymized_at = NONE
+                        AND (SELECT VALUE id FROM report WHERE user = $parent.id AND retrieved_at != NONE) != []
+                        AND time::max(array::flatten([
+                            [created_at ?? d"1970-01-01T00:00:00Z"],
+                            (SELECT VALUE retrieved_at ?? created_at FROM report WHERE user = $parent.id),
+                            (SELECT VALUE submitted_at ?? started_at FROM submission WHERE user = $parent.id),
+                            (SELECT VALUE assigned_at FROM assigned WHERE in = $parent.id AND assigned_at != NONE),
+                            (SELECT VALUE answered_at FROM says WHERE in = $parent.id AND answered_at != NONE)
+                        ])) < $CUTOFF;
+                "#,
+            )
+            .bind(("CUTOFF", Datetime::from(cutoff)))
+            .await?
+            .take::<Vec<String>>(0)?)
+    }
+
+    #[tracing::instrument(err)]
+    pub async fn mark_report_retrieved(&self, report_id: String) -> Result<()> {
+        DB.query(r#"UPDATE type::thing("report", $REPORT) SET retrieved_at = time::now()"#)
+            .bind(("REPORT", report_id))
+            .await?
+            .check()?;
         Ok(())
     }
 
+    /// Appends an entry to the audit log.
     #[tracing::instrument(err)]
-    pub async fn evaluate_test(&self, user_id: String) -> Result<Vec<TestResultRecord>> {
-        let res = DB.query(r#"
-            SELECT 
-                meta::id(out.id) AS question_id,
-                out.prompt AS question_txt,
-                (SELECT VALUE label FROM ONLY $this.out.options[$parent.choice]) AS answer_txt,
-                (SELECT VALUE value FROM ONLY $this.out.options[$parent.choice]) AS answer_value
-                FROM says WHERE meta::id(in) = $USER_ID AND out INSIDE (test:aq->contains->question) ORDER BY question_id NUMERIC
-            ;
-        "#).bind(("USER_ID", user_id.clone())).await?.take::<Vec<TestResultRecord>>(0)?;
-        crate::report::generate_pdf(user_id, &res).await?;
-        Ok(res)
+    pub async fn audit(
+        &self,
+        actor: Option<String>,
+        action: AuditAction,
+        patient: Option<String>,
+        details: String,
+    ) -> Result<()> {
+        DB.query(
+            r#"
+            CREATE audit SET
+                actor = $ACTOR,
+                action = $ACTION,
+                patient = $PATIENT,
+                details = $DETAILS;
+            "#,
+        )
+        .bind(("ACTOR", actor))
+        .bind(("ACTION", action))
+        .bind(("PATIENT", patient))
+        .bind(("DETAILS", details))
+        .await?
+        .check()?;
+        Ok(())
+    }
+
+    /// The audit log entries matching the filter, newest first. At most 1000 entries are returned.
+    #[tracing::instrument(err)]
+    pub async fn list_audit(&self, filter: AuditFilter) -> Result<Vec<AuditEntry>> {
+        let AuditFilter { patient, from, to } = filter;
+        let from = from.map_or(DateTime::UNIX_EPOCH, |day| {
+            day.and_time(NaiveTime::MIN).and_utc()
+        });
+        let to = to.map_or_else(Utc::now, |day| day.and_time(NaiveTime::MIN).and_utc())
+            + chrono::Duration::days(1);
+        Ok(DB
+            .query(
+                r#"
+                SELECT * FROM audit
+                    WHERE ($PATIENT = "" OR patient = $PATIENT) AND at >= $FROM AND at < $TO
+                    ORDER BY at DESC
+                    LIMIT 1000;
+                "#,
+            )
+            .bind(("PATIENT", patient.trim().to_string()))
+            .bind(("FROM", Datetime::from(from)))
+            .bind(("TO", Datetime::from(to)))
+            .await?
+            .take::<Vec<AuditRecord>>(0)?
+            .into_iter()
+            .map(AuditEntry::from)
+            .collect())
+    }
+
+    /// All patients matching the filter with the progress of their tests, newest first.
+    #[tracing::instrument(err)]
+    pub async fn list_patients(&self, filter: PatientFilter) -> Result<Vec<PatientOverview>> {
+        let PatientFilter {
+            case_number,
+            progress,
+        } = filter;
+        let users = DB
+            .query(
+                r#"
+                SELECT * FROM user
+                    WHERE anonymized_at = NONE AND (
+                        $CASE_NUMBER = ""
+                        OR string::contains(
+                            string::lowercase(info.case_number ?? ""),
+                            string::lowercase($CASE_NUMBER)
+                        )
+                    )
+                    ORDER BY created_at DESC;
+                "#,
+            )
+            .bind(("CASE_NUMBER", case_number.trim().to_string()))
+            .await?
+            .take::<Vec<UserRecord>>(0)?;
+
+        let users = users
+            .into_iter()
+            .map(User::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        let mut tests_by_user = self
+            .progress_of(users.iter().map(|u| u.id.clone()).collect())
+            .await?;
+
+        let mut patients = Vec::with_capacity(users.len());
+        for user in users {
+            let tests = tests_by_user.remove(&user.id).unwrap_or_default();
+            let patient = PatientOverview { user, tests };
+            let keep = match progress {
+                ProgressFilter::All => true,
+                ProgressFilter::Finished => patient.is_finished(),
+                ProgressFilter::Unfinished => !patient.is_finished(),
+            };
+            if keep {
+                patients.push(patient);
+            }
+        }
+        Ok(patients)
     }
 
+    /// The tests assigned to the user, each with the number of answered questions in the version
+    /// the user is answering.
     #[tracing::instrument(err)]
-    pub async fn get_assigned_tests(&self, user_id: String) -> Result<Vec<Test>> {
+    pub async fn get_assigned_tests(&self, user_id: String) -> Result<Vec<TestProgress>> {
+        Ok(self
+            .progress_of(vec![user_id.clone()])
+            .await?
+            .remove(&user_id)
+            .unwrap_or_default())
+    }
+
+    /// The progress of several users at once, keyed by user id. Loads assignments, submissions
+    /// and answers of all users together instead of querying per user and test.
+    async fn progress_of(
+        &self,
+        user_ids: Vec<String>,
+    ) -> Result<BTreeMap<String, Vec<TestProgress>>> {
+        let users = user_ids
+            .into_iter()
+            .map(|id| RecordId::from_table_key("user", id))
+            .collect::<Vec<_>>();
+        let mut response = DB
+            .query(
+                r#"
+                SELECT meta::id(in) AS user, meta::id(out) AS test, assigned_at FROM assigned
+                    WHERE in INSIDE $USERS ORDER BY assigned_at, test;
+                SELECT * FROM submission WHERE user INSIDE $USERS;
+                SELECT meta::id(in) AS user, meta::id(out) AS question_id FROM says
+                    WHERE in INSIDE $USERS;
+                SELECT * FROM test;
+                "#,
+            )
+            .bind(("USERS", users))
+            .await?;
+        let assignments = response.take::<Vec<AssignmentRecord>>(0)?;
+        let submissions = response
+            .take::<Vec<SubmissionRecord>>(1)?
+            .into_iter()
+            .map(|record| (record.user.key().to_string(), Submission::from(record)))
+            .collect::<Vec<_>>();
+        let mut answers: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for answer in response.take::<Vec<UserAnswerRecord>>(2)? {
+            answers
+                .entry(answer.user)
+                .or_default()
+                .insert(answer.question_id);
+        }
+        let tests = response
+            .take::<Vec<TestRecord>>(3)?
+            .into_iter()
+            .map(|record| {
+                let test = Test::from(record);
+                (test.id.clone(), test)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let mut versions: BTreeMap<(String, u32), Vec<Question>> = BTreeMap::new();
+        let mut progress: BTreeMap<String, Vec<TestProgress>> = BTreeMap::new();
+        for AssignmentRecord { user, test } in assignments {
+            let Some(test) = tests.get(&test).cloned() else {
+                continue;
+            };
+            let submission = submissions
+                .iter()
+                .find(|(u, s)| *u == user && s.test_id == test.id)
+                .map(|(_, s)| s);
+            let version = submission.map_or(test.version, |s| s.version);
+            let key = (test.id.clone(), version);
+            if !versions.contains_key(&key) {
+                let Questionnaire { questions, .. } =
+                    self.get_test_version(test.id.clone(), version).await?;
+                versions.insert(key.clone(), questions);
+            }
+            let questions = &versions[&key];
+            let answered = answers.get(&user).map_or(0, |answered| {
+                questions
+                    .iter()
+                    .filter(|q| answered.contains(&q.id))
+                    .count()
+            });
+            progress.entry(user).or_default().push(TestProgress {
+                test,
+                answered,
+                total: questions.len(),
+                submitted: submission.is_some_and(Submission::is_finished),
+            });
+        }
+        Ok(progress)
+    }
+
+    /// Fails unless the test is assigned to the user, so patients only see and answer their own
+    /// tests.
+    async fn ensure_assigned(&self, user_id: String, test_id: String) -> Result<()> {
+        let assigned = DB
+            .query(
+                r#"
+                SELECT VALUE id FROM assigned
+                    WHERE in = type::thing("user", $USER) AND out = type::thing("test", $TEST);
+                "#,
+            )
+            .bind(("USER", user_id))
+            .bind(("TEST", test_id.clone()))
+            .await?
+            .take::<Vec<RecordId>>(0)?;
+        if assigned.is_empty() {
+            color_eyre::eyre::bail!("Test `{test_id}` is not assigned to this patient");
+        }
+        Ok(())
+    }
+
+    async fn assigned_tests(&self, user_id: String) -> Result<Vec<Test>> {
         Ok(DB
             .query(
                 r#"
@@ -216,6 +1377,35 @@ impl From<TestRecord> for Test {
         Test {
             id: r.id.key().to_string(),
             name: r.name,
+            version: r.version,
+            subscales: r.subscales,
+            norms: r.norms,
+        }
+    }
+}
+
+impl TryFrom<ReportRecord> for Report {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(r: ReportRecord) -> Result<Report, Self::Error> {
+        Ok(Report {
+            id: r.id.key().clone().try_into()?,
+            test_id: r.test.key().to_string(),
+            test_name: r.test_name,
+            created_at: to_chrono(r.created_at),
+            score: r.score,
+        })
+    }
+}
+
+impl From<SubmissionRecord> for Submission {
+    fn from(r: SubmissionRecord) -> Submission {
+        Submission {
+            test_id: r.test.key().to_string(),
+            version: r.version,
+            status: r.status,
+            started_at: to_chrono(r.started_at),
+            submitted_at: r.submitted_at.map(to_chrono),
         }
     }
 }
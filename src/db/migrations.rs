@@ -0,0 +1,195 @@
+use super::DB;
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+
+/// A schema change that is applied exactly once per database.
+///
+/// Migrations are applied in order of `version` and never edited after release; changes to the
+/// schema get a new migration appended to [`MIGRATIONS`].
+struct Migration {
+    version: u32,
+    name: &'static str,
+    query: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial schema",
+        query: r#"
+            DEFINE TABLE IF NOT EXISTS user;
+            DEFINE FIELD IF NOT EXISTS retrieval_id ON TABLE user TYPE uuid READONLY VALUE rand::uuid();
+            DEFINE TABLE IF NOT EXISTS says TYPE RELATION IN user OUT question ENFORCED;
+            DEFINE TABLE IF NOT EXISTS report;
+            DEFINE TABLE IF NOT EXISTS test;
+            DEFINE TABLE IF NOT EXISTS question;
+            DEFINE TABLE IF NOT EXISTS contains TYPE RELATION IN test OUT question;
+        "#,
+    },
+    Migration {
+        version: 2,
+        name: "test versions and submissions",
+        query: r#"
+            DEFINE TABLE IF NOT EXISTS test_version;
+            DEFINE FIELD IF NOT EXISTS definition ON TABLE test_version TYPE object READONLY;
+            DEFINE TABLE IF NOT EXISTS submission;
+            DEFINE FIELD IF NOT EXISTS version ON TABLE submission TYPE int READONLY;
+        "#,
+    },
+    Migration {
+        version: 3,
+        name: "submission lifecycle",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS status ON TABLE submission TYPE string
+                ASSERT $value INSIDE ["in_progress", "submitted", "locked"]
+                DEFAULT "in_progress";
+            DEFINE FIELD IF NOT EXISTS started_at ON TABLE submission TYPE datetime
+                DEFAULT time::now() READONLY;
+            DEFINE FIELD IF NOT EXISTS submitted_at ON TABLE submission TYPE option<datetime>;
+            UPDATE submission SET status = "in_progress", started_at = time::now() WHERE status = NONE;
+        "#,
+    },
+    Migration {
+        version: 4,
+        name: "staff accounts and sessions",
+        query: r#"
+            DEFINE TABLE IF NOT EXISTS staff SCHEMAFULL;
+            DEFINE FIELD IF NOT EXISTS username ON TABLE staff TYPE string;
+            DEFINE FIELD IF NOT EXISTS password_hash ON TABLE staff TYPE string;
+            DEFINE TABLE IF NOT EXISTS session SCHEMAFULL;
+            DEFINE FIELD IF NOT EXISTS staff ON TABLE session TYPE record<staff>;
+            DEFINE FIELD IF NOT EXISTS created_at ON TABLE session TYPE datetime
+                DEFAULT time::now() READONLY;
+            DEFINE FIELD IF NOT EXISTS expires_at ON TABLE session TYPE datetime;
+        "#,
+    },
+    Migration {
+        version: 5,
+        name: "patient access tokens",
+        // `VALUE` is computed on every write, which made any update of a user fail against
+        // `READONLY`. `DEFAULT` only fills it in on creation.
+        query: r#"
+            DEFINE FIELD OVERWRITE retrieval_id ON TABLE user TYPE uuid DEFAULT rand::uuid() READONLY;
+            DEFINE FIELD IF NOT EXISTS access_token ON TABLE user TYPE string DEFAULT rand::string(48);
+            DEFINE FIELD IF NOT EXISTS access_expires_at ON TABLE user TYPE option<datetime>;
+            UPDATE user SET access_token = rand::string(48) WHERE access_token = NONE;
+            DEFINE INDEX IF NOT EXISTS user_access_token ON TABLE user FIELDS access_token UNIQUE;
+        "#,
+    },
+    Migration {
+        version: 6,
+        name: "single-use patient links",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS expire_when_done ON TABLE user TYPE bool DEFAULT false;
+            UPDATE user SET expire_when_done = false WHERE expire_when_done = NONE;
+        "#,
+    },
+    Migration {
+        version: 7,
+        name: "patient details",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS info ON TABLE user TYPE object DEFAULT {};
+            DEFINE FIELD IF NOT EXISTS info.case_number ON TABLE user TYPE option<string>;
+            DEFINE FIELD IF NOT EXISTS info.birth_year ON TABLE user TYPE option<int>
+                ASSERT $value = NONE OR ($value >= 1900 AND $value <= time::year(time::now()));
+            DEFINE FIELD IF NOT EXISTS info.sex ON TABLE user TYPE option<string>
+                ASSERT $value = NONE OR $value INSIDE ["female", "male", "diverse"];
+            DEFINE FIELD IF NOT EXISTS info.clinician ON TABLE user TYPE option<string>;
+            DEFINE INDEX IF NOT EXISTS user_case_number ON TABLE user FIELDS info.case_number;
+            UPDATE user SET info = {} WHERE info = NONE;
+        "#,
+    },
+    Migration {
+        version: 8,
+        name: "user creation date",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS created_at ON TABLE user TYPE option<datetime>
+                DEFAULT time::now() READONLY;
+        "#,
+    },
+    Migration {
+        version: 9,
+        name: "anonymized users",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS anonymized_at ON TABLE user TYPE option<datetime>;
+        "#,
+    },
+    Migration {
+        version: 10,
+        name: "retention and audit log",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS retrieved_at ON TABLE report TYPE option<datetime>;
+            DEFINE TABLE IF NOT EXISTS audit SCHEMAFULL;
+            DEFINE FIELD IF NOT EXISTS at ON TABLE audit TYPE datetime DEFAULT time::now() READONLY;
+            DEFINE FIELD IF NOT EXISTS actor ON TABLE audit TYPE option<string> READONLY;
+            DEFINE FIELD IF NOT EXISTS action ON TABLE audit TYPE string READONLY;
+            DEFINE FIELD IF NOT EXISTS patient ON TABLE audit TYPE option<string> READONLY;
+            DEFINE FIELD IF NOT EXISTS details ON TABLE audit TYPE string READONLY;
+            DEFINE INDEX IF NOT EXISTS audit_at ON TABLE audit FIELDS at;
+            DEFINE INDEX IF NOT EXISTS audit_patient ON TABLE audit FIELDS patient;
+        "#,
+    },
+    Migration {
+        version: 11,
+        name: "append-only audit log",
+        query: r#"
+            DEFINE EVENT IF NOT EXISTS audit_append_only ON TABLE audit
+                WHEN $event != "CREATE"
+                THEN { THROW "The audit log is append-only" };
+        "#,
+    },
+    Migration {
+        version: 12,
+        name: "activity timestamps",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS assigned_at ON TABLE assigned TYPE option<datetime> DEFAULT time::now() READONLY;
+            DEFINE FIELD IF NOT EXISTS answered_at ON TABLE says TYPE option<datetime> DEFAULT time::now() READONLY;
+        "#,
+    },
+    Migration {
+        version: 13,
+        name: "reopenable links",
+        query: r#"
+            DEFINE FIELD IF NOT EXISTS done_at ON TABLE user TYPE option<datetime>;
+        "#,
+    },
+];
+
+/// Applies all migrations newer than the version recorded in the database.
+#[tracing::instrument(err)]
+pub(super) async fn run() -> Result<()> {
+    DB.query("DEFINE TABLE IF NOT EXISTS migration SCHEMALESS;")
+        .await?
+        .check()?;
+    let applied = DB
+        .query("SELECT VALUE version FROM migration")
+        .await?
+        .take::<Vec<u32>>(0)?
+        .into_iter()
+        .max()
+        .unwrap_or(0);
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > applied) {
+        let Migration {
+            version,
+            name,
+            query,
+        } = migration;
+        tracing::info!("Applying migration {version}: {name}");
+        DB.query("BEGIN TRANSACTION")
+            .query(*query)
+            .query(
+                r#"
+                CREATE type::thing("migration", $VERSION)
+                    SET version = $VERSION, name = $NAME, applied_at = time::now();
+                "#,
+            )
+            .query("COMMIT TRANSACTION")
+            .bind(("VERSION", *version))
+            .bind(("NAME", *name))
+            .await?
+            .check()
+            .wrap_err_with(|| format!("Migration {version} ({name}) failed"))?;
+    }
+    Ok(())
+}
@@ -1,12 +1,24 @@
 pub mod app;
 pub mod types;
 
+#[cfg(feature = "ssr")]
+pub mod auth;
+
+#[cfg(feature = "ssr")]
+pub mod catalog;
+
+#[cfg(feature = "ssr")]
+pub mod config;
+
 #[cfg(feature = "ssr")]
 pub mod report;
 
 #[cfg(feature = "ssr")]
 pub mod db;
 
+#[cfg(feature = "ssr")]
+pub mod retention;
+
 #[cfg(feature = "hydrate")]
 #[wasm_bindgen::prelude::wasm_bindgen]
 pub fn hydrate() {
@@ -1,7 +1,7 @@
 #[cfg(feature = "ssr")]
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
-    use axum::Router;
+    use axum::{routing::get, Router};
     use dellplatz_diag::app::*;
     use leptos::prelude::*;
     use leptos_axum::{generate_route_list, LeptosRoutes};
@@ -9,7 +9,17 @@ async fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
     tracing_subscriber::fmt::init();
 
-    dellplatz_diag::db::Db::connect().await.unwrap();
+    dellplatz_diag::config::load()?;
+    let db = dellplatz_diag::db::Db::connect().await?;
+
+    let mut args = std::env::args().skip(1);
+    match (args.next().as_deref(), args.next()) {
+        (None, _) => {}
+        (Some("add-staff"), Some(username)) => return add_staff(&db, username).await,
+        _ => color_eyre::eyre::bail!("Usage: dellplatz-diag [add-staff <username>]"),
+    }
+
+    tokio::spawn(dellplatz_diag::retention::run());
 
     let conf = get_configuration(None).unwrap();
     let addr = conf.leptos_options.site_addr;
@@ -18,6 +28,10 @@ async fn main() -> color_eyre::Result<()> {
     let routes = generate_route_list(App);
 
     let app = Router::new()
+        .route(
+            "/reports/{retrieval_id}/{file}",
+            get(dellplatz_diag::report::download),
+        )
         .leptos_routes(&leptos_options, routes, {
             let leptos_options = leptos_options.clone();
             move || shell(leptos_options.clone())
@@ -33,6 +47,22 @@ async fn main() -> color_eyre::Result<()> {
     color_eyre::eyre::bail!("HTTP server stopped unexpectedly")
 }
 
+/// Creates a staff account or resets its password. The password is read from stdin.
+#[cfg(feature = "ssr")]
+async fn add_staff(db: &dellplatz_diag::db::Db, username: String) -> color_eyre::Result<()> {
+    eprintln!("Password for {username}:");
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+    color_eyre::eyre::ensure!(
+        password.chars().count() >= 12,
+        "The password must have at least 12 characters"
+    );
+    db.set_staff_password(username.clone(), password).await?;
+    tracing::info!("Staff account {username} saved");
+    Ok(())
+}
+
 #[cfg(not(feature = "ssr"))]
 pub fn main() {
     // no client-side main function
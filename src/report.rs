@@ -1,15 +1,22 @@
 use crate::types::*;
+use axum::extract;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
 use color_eyre::Result;
 use reqwest::multipart;
 use reqwest::Client;
+use std::path::PathBuf;
+use uuid::Uuid;
 
-const API_ENDPOINT: &str = "http://localhost:30000/forms/chromium/convert/html";
+const CONVERT_HTML_PATH: &str = "/forms/chromium/convert/html";
 
 #[tracing::instrument(err)]
-pub(crate) async fn generate_pdf(user_id: String, res: &Vec<TestResultRecord>) -> Result<()> {
-    let html = format_html(user_id, res);
+pub(crate) async fn generate_pdf(user: &User, evaluation: &Evaluation) -> Result<Vec<u8>> {
+    let html = format_html(user, evaluation);
 
-    let client = Client::new();
+    let config = &crate::config::get().gotenberg;
+    let endpoint = format!("{}{CONVERT_HTML_PATH}", config.url.trim_end_matches('/'));
+    let client = Client::builder().timeout(config.timeout()).build()?;
 
     let part = multipart::Part::text(html.to_string())
         .file_name("index.html")
@@ -17,23 +24,144 @@ pub(crate) async fn generate_pdf(user_id: String, res: &Vec<TestResultRecord>) -
 
     let form = multipart::Form::new().part("files", part);
 
-    let resp = client.post(API_ENDPOINT).multipart(form).send().await?;
+    let resp = client.post(endpoint).multipart(form).send().await?;
 
     if !resp.status().is_success() {
         let err = resp.text().await?;
         color_eyre::eyre::bail!("Failed to generate PDF: {err}");
     }
 
-    let bytes = resp.bytes().await?;
-    tokio::fs::write("output.pdf", &bytes).await?;
+    Ok(resp.bytes().await?.to_vec())
+}
+
+/// Location of a stored report, `file` being relative to the configured report directory.
+fn report_path(file: &str) -> PathBuf {
+    crate::config::get().reports.dir.join(file)
+}
+
+#[tracing::instrument(err, skip(bytes))]
+pub(crate) async fn write_report(file: &str, bytes: &[u8]) -> Result<()> {
+    let path = report_path(file);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(&path, bytes).await?;
+    tracing::info!("PDF written to {}", path.display());
+    Ok(())
+}
 
-    tracing::info!("PDF written to output.pdf");
+#[tracing::instrument(err)]
+pub(crate) async fn read_report(file: &str) -> Result<Vec<u8>> {
+    Ok(tokio::fs::read(report_path(file)).await?)
+}
 
+/// Removes a stored report, and its directory once that is empty. Missing files are ignored.
+#[tracing::instrument(err)]
+pub(crate) async fn delete_report(file: &str) -> Result<()> {
+    let path = report_path(file);
+    match tokio::fs::remove_file(&path).await {
+        Ok(()) => tracing::info!("PDF {} deleted", path.display()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    if let Some(dir) = path.parent() {
+        // Fails while other reports are left in the directory.
+        let _ = tokio::fs::remove_dir(dir).await;
+    }
     Ok(())
 }
 
+/// Serves the newest report of a test at `/reports/{retrieval_id}/{test}.pdf`.
+///
+/// Reports are looked up by retrieval id only, so patient links never give access to them. Only
+/// logged in staff can download them.
+pub async fn download(
+    staff: Staff,
+    extract::Path((retrieval_id, file)): extract::Path<(Uuid, String)>,
+) -> Response {
+    let Some(test_id) = file.strip_suffix(".pdf") else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match find_report(retrieval_id, test_id.to_string(), &staff).await {
+        Ok(Some((report, pdf))) => {
+            let filename = format!(
+                "{}-{}.pdf",
+                report.test_id,
+                report.created_at.format("%Y-%m-%d")
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, String::from("application/pdf")),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!(r#"attachment; filename="{filename}""#),
+                    ),
+                ],
+                pdf,
+            )
+                .into_response()
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[tracing::instrument(err)]
+async fn find_report(
+    retrieval_id: Uuid,
+    test_id: String,
+    staff: &Staff,
+) -> Result<Option<(Report, Vec<u8>)>> {
+    let db = crate::db::Db::get();
+    let Some(user) = db.find_user_by_retrieval_id(retrieval_id).await? else {
+        return Ok(None);
+    };
+    let found = db.get_latest_report_pdf(user.id.clone(), test_id).await?;
+    if let Some((report, _)) = &found {
+        db.mark_report_retrieved(report.id.clone()).await?;
+        db.audit(
+            Some(staff.username.clone()),
+            AuditAction::ReportDownloaded,
+            Some(user.id),
+            format!("Bericht {} heruntergeladen", report.test_name),
+        )
+        .await?;
+    }
+    Ok(found)
+}
+
 const STYLE: &str = include_str!("../assets/report.css");
 
+fn format_patient(info: &PatientInfo) -> String {
+    let PatientInfo {
+        case_number,
+        birth_year,
+        sex,
+        clinician,
+    } = info;
+    let rows = [
+        ("Fallnummer", case_number.as_deref().map(escape_html)),
+        ("Geburtsjahr", birth_year.map(|year| year.to_string())),
+        ("Geschlecht", sex.map(|sex| sex.label().to_string())),
+        ("Behandler/in", clinician.as_deref().map(escape_html)),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| Some(format!("<tr><th>{label}</th><td>{}</td></tr>", value?)))
+    .collect::<String>();
+    if rows.is_empty() {
+        return String::new();
+    }
+    format!(r#"<table class="patient">{rows}</table>"#)
+}
+
+/// Escapes text entered by staff before it is put into the report.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 fn format_row(row: &TestResultRecord) -> String {
     let TestResultRecord {
         question_txt,
@@ -41,6 +169,7 @@ fn format_row(row: &TestResultRecord) -> String {
         answer_value,
         ..
     } = row;
+    let answer_value = answer_value.map_or_else(|| String::from("–"), |v| v.to_string());
     format!(
         r"
 <tr>
@@ -52,9 +181,78 @@ fn format_row(row: &TestResultRecord) -> String {
     )
 }
 
-fn format_html(user_id: String, rows: &Vec<TestResultRecord>) -> String {
-    let sum = rows.into_iter().map(|r| r.answer_value).sum::<usize>();
-    let rows = rows.into_iter().map(format_row).collect::<String>();
+fn format_band(band: &Option<Band>) -> String {
+    match band {
+        Some(Band {
+            label,
+            clinical: true,
+            ..
+        }) => format!(r#"<span class="band clinical">{label}</span>"#),
+        Some(Band { label, .. }) => format!(r#"<span class="band">{label}</span>"#),
+        None => String::new(),
+    }
+}
+
+fn format_subscale_row(subscale: &SubscaleScore) -> String {
+    let SubscaleScore {
+        name, sum, band, ..
+    } = subscale;
+    let band = format_band(band);
+    format!(
+        r"
+<tr>
+<td>{name}</td>
+<td>{sum}</td>
+<td>{band}</td>
+</tr>
+    "
+    )
+}
+
+fn format_subscales(score: &Score) -> String {
+    if score.subscales.is_empty() {
+        return String::new();
+    }
+    let rows = score
+        .subscales
+        .iter()
+        .map(format_subscale_row)
+        .collect::<String>();
+    format!(
+        r#"
+    <table class="subscales">
+      <tr>
+        <th>Subskala</th>
+        <th>Score</th>
+        <th>Einordnung</th>
+      </tr>
+      {rows}
+    </table>
+"#
+    )
+}
+
+fn format_html(user: &User, evaluation: &Evaluation) -> String {
+    let Evaluation {
+        test,
+        results,
+        score,
+        ..
+    } = evaluation;
+    let user_id = &user.id;
+    let patient = format_patient(&user.info);
+    let test_name = &test.name;
+    let test_version = test.version;
+    let sum = score.total;
+    let band_row = match &score.band {
+        Some(_) => {
+            let band = format_band(&score.band);
+            format!("<tr><td colspan=2>EINORDNUNG</td><td>{band}</td></tr>")
+        }
+        None => String::new(),
+    };
+    let subscales = format_subscales(score);
+    let rows = results.iter().map(format_row).collect::<String>();
     format!(
         r#"
 <!doctype html>
@@ -65,11 +263,12 @@ fn format_html(user_id: String, rows: &Vec<TestResultRecord>) -> String {
     </style>
   </head>
   <body>
-    <h2>Test Report AQ</h2>
+    <h2>Test Report {test_name} <small>(Version {test_version})</small></h2>
     <div class="patienten-id">
     <b>Patienten ID:</b>
     <pre>{user_id}</pre>
     </div>
+    {patient}
 
     <table>
       <tr>
@@ -83,8 +282,10 @@ fn format_html(user_id: String, rows: &Vec<TestResultRecord>) -> String {
       <td colspan=2>SUMME</td>
       <td>{sum}</td>
       </tr>
+      {band_row}
       </tfoot>
     </table>
+    {subscales}
   </body>
 </html>
 "#
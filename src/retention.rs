@@ -0,0 +1,54 @@
+use crate::db::Db;
+use crate::types::*;
+use color_eyre::Result;
+
+/// Periodically deletes patients with downloaded reports who have seen no activity for the
+/// configured retention period. Returns right away when no retention period is configured.
+pub async fn run() {
+    let config = &crate::config::get().retention;
+    let Some(days) = config.days else {
+        tracing::info!("No retention period configured, patient data is kept");
+        return;
+    };
+    let mut interval = tokio::time::interval(config.check_interval());
+    loop {
+        interval.tick().await;
+        if let Err(err) = purge(days).await {
+            tracing::error!("Purging expired patient data failed: {err:?}");
+        }
+    }
+}
+
+/// Erases every expired patient. A failure only skips that patient, the others are still purged.
+#[tracing::instrument(err)]
+async fn purge(days: u32) -> Result<()> {
+    let db = Db::get();
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(days.into());
+    for user_id in db.find_expired_users(cutoff).await? {
+        let erased = match db.erase_user(user_id.clone(), Erasure::Delete).await {
+            Ok(erased) => erased,
+            Err(err) => {
+                tracing::error!("Purging patient {user_id} failed: {err:?}");
+                continue;
+            }
+        };
+        let ErasedData {
+            answers, reports, ..
+        } = erased;
+        let note = erased.undeleted_files_note();
+        let audited = db
+            .audit(
+                None,
+                AuditAction::RetentionPurge,
+                Some(user_id.clone()),
+                format!(
+                    "Aufbewahrungsfrist von {days} Tagen abgelaufen: Patient, {answers} Antworten und {reports} Berichte gelöscht{note}"
+                ),
+            )
+            .await;
+        if let Err(err) = audited {
+            tracing::error!("Logging the purge of patient {user_id} failed: {err:?}");
+        }
+    }
+    Ok(())
+}
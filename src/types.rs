@@ -1,42 +1,853 @@
-use std::collections::BTreeMap;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::{BTreeMap, BTreeSet};
 use uuid::Uuid;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Opt {
     pub value: usize,
     pub label: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Question {
     pub id: String,
     pub prompt: String,
     pub options: Vec<Opt>,
+    #[serde(default)]
+    pub key: ScoringKey,
+    #[serde(default = "default_weight")]
+    pub weight: usize,
+    /// Ids of the subscales this item counts toward.
+    #[serde(default)]
+    pub subscales: Vec<String>,
+}
+
+/// How the value of the chosen option enters the test score.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringKey {
+    #[default]
+    Forward,
+    /// The option values are mirrored, e.g. `0,0,1,1` scores as `1,1,0,0`.
+    Reverse,
+    /// The item is shown and stored but does not count toward the score.
+    Unscored,
+}
+
+fn default_weight() -> usize {
+    1
+}
+
+impl Question {
+    /// Scores the option at index `choice`, or `None` if the item does not count.
+    pub fn score(&self, choice: usize) -> Option<usize> {
+        let value = self.options.get(choice)?.value;
+        let value = match self.key {
+            ScoringKey::Forward => value,
+            ScoringKey::Reverse => {
+                let min = self.options.iter().map(|o| o.value).min()?;
+                let max = self.options.iter().map(|o| o.value).max()?;
+                max + min - value
+            }
+            ScoringKey::Unscored => return None,
+        };
+        Some(value * self.weight)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct User {
     pub id: String,
     pub retrieval_id: Uuid,
+    /// Identifies the patient in their links. Only resolved on the server.
+    pub access_token: String,
+    pub access_expires_at: Option<DateTime<Utc>>,
+    /// Expire the link once every assigned test has been submitted.
+    pub expire_when_done: bool,
+    /// When the last assigned test was submitted. Cleared when another test is assigned.
+    pub done_at: Option<DateTime<Utc>>,
+    pub info: PatientInfo,
+    /// Unknown for users created before it was recorded.
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl User {
+    /// Whether the patient link can no longer be used at `now`.
+    pub fn link_expired(&self, now: DateTime<Utc>) -> bool {
+        self.access_expires_at.is_some_and(|date| date <= now)
+            || (self.expire_when_done && self.done_at.is_some())
+    }
+}
+
+/// Pseudonymous details about a patient. Everything is optional.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct PatientInfo {
+    /// The practice's internal case number.
+    pub case_number: Option<String>,
+    pub birth_year: Option<i32>,
+    pub sex: Option<Sex>,
+    /// The clinician responsible for the patient.
+    pub clinician: Option<String>,
+}
+
+impl PatientInfo {
+    /// The demographics used for norm lookups in `year`. Only the birth year is known, so the age
+    /// may be one year too high.
+    pub fn demographics(&self, year: i32) -> Demographics {
+        Demographics {
+            age: self
+                .birth_year
+                .and_then(|born| u32::try_from(year - born).ok()),
+            sex: self.sex,
+        }
+    }
+}
+
+/// What staff enter when generating a patient link.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewUser {
+    pub tests: BTreeSet<String>,
+    /// Days the link stays valid, `None` for no expiry.
+    pub valid_days: Option<u32>,
+    pub expire_when_done: bool,
+    pub info: PatientInfo,
+}
+
+/// How the data of a patient is erased.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Erasure {
+    /// Removes the patient with all answers and reports.
+    Delete,
+    /// Keeps the answers for statistics but removes the patient details and reports, and makes
+    /// the patient unreachable through any link.
+    Anonymize,
+}
+
+/// What was removed when erasing a patient.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct ErasedData {
+    pub answers: usize,
+    pub reports: usize,
+    /// Report files that could not be deleted from disk, although their records are gone.
+    pub undeleted_files: usize,
+}
+
+impl ErasedData {
+    /// A note for the audit log about report files left on disk, empty if there are none.
+    pub fn undeleted_files_note(&self) -> String {
+        match self.undeleted_files {
+            0 => String::new(),
+            count => format!(" ({count} PDF-Dateien konnten nicht gelöscht werden)"),
+        }
+    }
+}
+
+/// What an audit log entry records.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// Staff generated a patient link.
+    PatientCreated,
+    /// Staff opened the retrieval page of a patient.
+    RetrievalOpened,
+    /// Staff generated a PDF report.
+    ReportGenerated,
+    /// Staff downloaded a PDF report.
+    ReportDownloaded,
+    /// Staff deleted a patient.
+    PatientDeleted,
+    /// Staff anonymized a patient.
+    PatientAnonymized,
+    /// A patient was deleted after the retention period.
+    RetentionPurge,
+}
+
+impl AuditAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            AuditAction::PatientCreated => "Patient angelegt",
+            AuditAction::RetrievalOpened => "Abruf geöffnet",
+            AuditAction::ReportGenerated => "Bericht erstellt",
+            AuditAction::ReportDownloaded => "Bericht heruntergeladen",
+            AuditAction::PatientDeleted => "Patient gelöscht",
+            AuditAction::PatientAnonymized => "Patient anonymisiert",
+            AuditAction::RetentionPurge => "Aufbewahrungsfrist abgelaufen",
+        }
+    }
+}
+
+/// One entry of the audit log.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    /// The staff member, `None` for actions taken by the server itself.
+    pub actor: Option<String>,
+    pub action: AuditAction,
+    /// The id of the patient, which stays in the log after the patient is erased.
+    pub patient: Option<String>,
+    pub details: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct AuditFilter {
+    /// The id of a patient. Empty matches every entry.
+    pub patient: String,
+    /// First day to include.
+    pub from: Option<NaiveDate>,
+    /// Last day to include.
+    pub to: Option<NaiveDate>,
+}
+
+/// Whether a patient link can still be used.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    Valid,
+    Expired,
+    Unknown,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Data {
-    pub user: User,
+    pub user: UserRef,
+    pub test: TestVersion,
     pub q: BTreeMap<String, usize>,
 }
+
+/// The user a form submission is written for, given by their access token.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserRef {
+    pub token: String,
+}
+
+/// Identifies the exact wording a set of answers was given to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TestVersion {
+    pub id: String,
+    pub version: u32,
+}
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TestResultRecord {
     pub answer_txt: String,
-    pub answer_value: usize,
+    pub answer_value: Option<usize>,
     pub question_txt: String,
     pub question_id: String,
+    pub subscales: Vec<String>,
+}
+
+impl TestResultRecord {
+    pub fn new(question: &Question, choice: usize) -> Option<Self> {
+        Some(Self {
+            answer_txt: question.options.get(choice)?.label.clone(),
+            answer_value: question.score(choice),
+            question_txt: question.prompt.clone(),
+            question_id: question.id.clone(),
+            subscales: question.subscales.clone(),
+        })
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Test {
     pub id: String,
     pub name: String,
+    pub version: u32,
+    #[serde(default)]
+    pub subscales: Vec<Subscale>,
+    #[serde(default)]
+    pub norms: Vec<Norm>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct Subscale {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Sex {
+    Female,
+    Male,
+    Diverse,
+}
+
+impl Sex {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Sex::Female => "weiblich",
+            Sex::Male => "männlich",
+            Sex::Diverse => "divers",
+        }
+    }
+}
+
+/// What is known about a patient when looking up norms.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Demographics {
+    pub age: Option<u32>,
+    pub sex: Option<Sex>,
+}
+
+/// Interpretation bands for the total score or one subscale.
+///
+/// A norm restricted by sex or age only applies if the patient is known to match it. The first
+/// applicable norm of a scale wins, so specific norms go before general ones.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct Norm {
+    /// Subscale id, or `None` for the total score.
+    #[serde(default)]
+    pub subscale: Option<String>,
+    #[serde(default)]
+    pub sex: Option<Sex>,
+    #[serde(default)]
+    pub min_age: Option<u32>,
+    #[serde(default)]
+    pub max_age: Option<u32>,
+    pub bands: Vec<Band>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct Band {
+    pub min: usize,
+    /// Inclusive upper bound, `None` for open-ended bands.
+    #[serde(default)]
+    pub max: Option<usize>,
+    pub label: String,
+    /// Whether the band lies above a clinical cutoff.
+    #[serde(default)]
+    pub clinical: bool,
+}
+
+impl Norm {
+    fn applies_to(&self, demographics: &Demographics) -> bool {
+        let sex = self.sex.is_none() || self.sex == demographics.sex;
+        let age = match (self.min_age, self.max_age) {
+            (None, None) => true,
+            (min, max) => demographics.age.is_some_and(|age| {
+                min.is_none_or(|min| age >= min) && max.is_none_or(|max| age <= max)
+            }),
+        };
+        sex && age
+    }
+
+    fn band(&self, value: usize) -> Option<&Band> {
+        self.bands
+            .iter()
+            .find(|b| value >= b.min && b.max.is_none_or(|max| value <= max))
+    }
+}
+
+impl Test {
+    /// Looks up the interpretation band of `value` on the total score (`subscale == None`) or a
+    /// subscale.
+    pub fn band(
+        &self,
+        subscale: Option<&str>,
+        value: usize,
+        demographics: &Demographics,
+    ) -> Option<Band> {
+        self.norms
+            .iter()
+            .filter(|n| n.subscale.as_deref() == subscale)
+            .find(|n| n.applies_to(demographics))?
+            .band(value)
+            .cloned()
+    }
+}
+
+/// A questionnaire as it is described by a JSON file in the data directory.
+///
+/// Once a version is in use its content is frozen; any change to the wording or scoring needs a
+/// new `version`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct TestDefinition {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub subscales: Vec<Subscale>,
+    #[serde(default)]
+    pub norms: Vec<Norm>,
+    pub questions: Vec<Question>,
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+impl From<TestDefinition> for Questionnaire {
+    fn from(definition: TestDefinition) -> Questionnaire {
+        let TestDefinition {
+            id,
+            name,
+            version,
+            subscales,
+            norms,
+            questions,
+        } = definition;
+        Questionnaire {
+            test: Test {
+                id,
+                name,
+                version,
+                subscales,
+                norms,
+            },
+            questions,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Questionnaire {
+    pub test: Test,
+    pub questions: Vec<Question>,
+}
+
+/// A question together with the choice the user has saved for it so far.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnsweredQuestion {
+    pub question: Question,
+    pub choice: Option<usize>,
+}
+
+/// The questionnaire as the user left it, used to fill in the form again.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnsweredQuestionnaire {
+    pub test: Test,
+    pub questions: Vec<AnsweredQuestion>,
+    /// Submitted answers can only be viewed, not changed.
+    pub submitted: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SubscaleScore {
+    pub id: String,
+    pub name: String,
+    pub sum: usize,
+    pub band: Option<Band>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Score {
+    pub total: usize,
+    pub band: Option<Band>,
+    pub subscales: Vec<SubscaleScore>,
+}
+
+impl Score {
+    pub fn new(test: &Test, results: &[TestResultRecord], demographics: &Demographics) -> Self {
+        let total = results.iter().filter_map(|r| r.answer_value).sum();
+        let subscales = test
+            .subscales
+            .iter()
+            .map(|Subscale { id, name }| {
+                let sum = results
+                    .iter()
+                    .filter(|r| r.subscales.contains(id))
+                    .filter_map(|r| r.answer_value)
+                    .sum();
+                SubscaleScore {
+                    id: id.clone(),
+                    name: name.clone(),
+                    sum,
+                    band: test.band(Some(id), sum, demographics),
+                }
+            })
+            .collect();
+        Score {
+            total,
+            band: test.band(None, total, demographics),
+            subscales,
+        }
+    }
+}
+
+/// The scored answers of one user for one test.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Evaluation {
+    pub test: Test,
+    pub results: Vec<TestResultRecord>,
+    pub score: Score,
+    pub question_count: usize,
+}
+
+impl Evaluation {
+    pub fn is_complete(&self) -> bool {
+        self.results.len() == self.question_count
+    }
+}
+
+/// Metadata of a stored PDF report.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Report {
+    pub id: String,
+    pub test_id: String,
+    pub test_name: String,
+    pub created_at: DateTime<Utc>,
+    pub score: Score,
+}
+
+/// An assigned test as seen by the clinician.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TestStatus {
+    pub submission: Option<Submission>,
+    pub evaluation: Evaluation,
+    /// Stored reports, newest first.
+    pub reports: Vec<Report>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Retrieval {
+    pub user: User,
+    pub tests: Vec<TestStatus>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionStatus {
+    InProgress,
+    Submitted,
+    /// Closed by a clinician, answers can no longer be changed.
+    Locked,
+}
+
+/// The answers of one user to one test.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Submission {
+    pub test_id: String,
+    pub version: u32,
+    pub status: SubmissionStatus,
+    pub started_at: DateTime<Utc>,
+    pub submitted_at: Option<DateTime<Utc>>,
+}
+
+impl Submission {
+    /// Whether the answers were submitted, after which the patient can no longer change them.
+    pub fn is_finished(&self) -> bool {
+        self.status != SubmissionStatus::InProgress
+    }
+}
+
+/// A logged in clinician.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Staff {
+    pub username: String,
+}
+
+/// How far a user has got with an assigned test.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TestProgress {
+    pub test: Test,
+    pub answered: usize,
+    pub total: usize,
+    pub submitted: bool,
+}
+
+/// A patient as listed on the staff dashboard.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PatientOverview {
+    pub user: User,
+    pub tests: Vec<TestProgress>,
+}
+
+impl PatientOverview {
+    /// Whether every assigned test has been submitted.
+    pub fn is_finished(&self) -> bool {
+        !self.tests.is_empty() && self.tests.iter().all(|t| t.submitted)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct PatientFilter {
+    /// Part of the case number, matched case-insensitively. Empty matches every patient.
+    pub case_number: String,
+    pub progress: ProgressFilter,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressFilter {
+    #[default]
+    All,
+    Finished,
+    Unfinished,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question(id: &str, key: ScoringKey, weight: usize, values: &[usize]) -> Question {
+        Question {
+            id: id.to_string(),
+            prompt: format!("Frage {id}"),
+            options: values
+                .iter()
+                .map(|&value| Opt {
+                    value,
+                    label: value.to_string(),
+                })
+                .collect(),
+            key,
+            weight,
+            subscales: Vec::new(),
+        }
+    }
+
+    fn test(subscales: &[&str], norms: Vec<Norm>) -> Test {
+        Test {
+            id: String::from("test"),
+            name: String::from("Test"),
+            version: 1,
+            subscales: subscales
+                .iter()
+                .map(|id| Subscale {
+                    id: id.to_string(),
+                    name: id.to_uppercase(),
+                })
+                .collect(),
+            norms,
+        }
+    }
+
+    #[test]
+    fn forward_items_score_the_option_value() {
+        let q = question("q", ScoringKey::Forward, 1, &[0, 1, 2, 3]);
+        assert_eq!(q.score(0), Some(0));
+        assert_eq!(q.score(2), Some(2));
+        assert_eq!(q.score(3), Some(3));
+    }
+
+    #[test]
+    fn reverse_items_mirror_the_option_values() {
+        let q = question("q", ScoringKey::Reverse, 1, &[0, 0, 1, 1]);
+        assert_eq!(q.score(0), Some(1));
+        assert_eq!(q.score(1), Some(1));
+        assert_eq!(q.score(2), Some(0));
+        assert_eq!(q.score(3), Some(0));
+
+        let q = question("q", ScoringKey::Reverse, 1, &[1, 2, 3, 4, 5]);
+        assert_eq!(q.score(0), Some(5));
+        assert_eq!(q.score(2), Some(3));
+        assert_eq!(q.score(4), Some(1));
+    }
+
+    #[test]
+    fn unscored_items_do_not_count() {
+        let q = question("q", ScoringKey::Unscored, 1, &[0, 1, 2]);
+        assert_eq!(q.score(0), None);
+        assert_eq!(q.score(2), None);
+    }
+
+    #[test]
+    fn weights_multiply_the_keyed_value() {
+        let q = question("q", ScoringKey::Forward, 3, &[0, 1, 2]);
+        assert_eq!(q.score(2), Some(6));
+
+        let q = question("q", ScoringKey::Reverse, 2, &[0, 1, 2]);
+        assert_eq!(q.score(0), Some(4));
+        assert_eq!(q.score(2), Some(0));
+
+        let q = question("q", ScoringKey::Unscored, 5, &[0, 1, 2]);
+        assert_eq!(q.score(1), None);
+    }
+
+    #[test]
+    fn out_of_range_choices_do_not_score() {
+        for key in [
+            ScoringKey::Forward,
+            ScoringKey::Reverse,
+            ScoringKey::Unscored,
+        ] {
+            let q = question("q", key, 2, &[0, 1]);
+            assert_eq!(q.score(2), None);
+            assert_eq!(q.score(usize::MAX), None);
+            assert!(TestResultRecord::new(&q, 2).is_none());
+        }
+    }
+
+    #[test]
+    fn score_sums_total_and_subscales() {
+        let mut a = question("a", ScoringKey::Forward, 1, &[0, 1, 2]);
+        a.subscales = vec![String::from("x")];
+        let mut b = question("b", ScoringKey::Reverse, 2, &[0, 1, 2]);
+        b.subscales = vec![String::from("x"), String::from("y")];
+        let mut c = question("c", ScoringKey::Unscored, 1, &[0, 1, 2]);
+        c.subscales = vec![String::from("y")];
+        let results = [
+            TestResultRecord::new(&a, 2).unwrap(),
+            TestResultRecord::new(&b, 0).unwrap(),
+            TestResultRecord::new(&c, 2).unwrap(),
+        ];
+
+        let score = Score::new(
+            &test(&["x", "y"], Vec::new()),
+            &results,
+            &Demographics::default(),
+        );
+        assert_eq!(score.total, 6);
+        assert_eq!(score.band, None);
+        let sums = score
+            .subscales
+            .iter()
+            .map(|s| (s.id.as_str(), s.sum))
+            .collect::<Vec<_>>();
+        assert_eq!(sums, [("x", 6), ("y", 4)]);
+    }
+
+    #[test]
+    fn score_of_no_answers_is_zero() {
+        let score = Score::new(&test(&["x"], Vec::new()), &[], &Demographics::default());
+        assert_eq!(score.total, 0);
+        assert_eq!(score.subscales.len(), 1);
+        assert_eq!(score.subscales[0].sum, 0);
+    }
+
+    fn band(min: usize, max: Option<usize>, label: &str, clinical: bool) -> Band {
+        Band {
+            min,
+            max,
+            label: label.to_string(),
+            clinical,
+        }
+    }
+
+    fn norm(sex: Option<Sex>, ages: (Option<u32>, Option<u32>), label: &str) -> Norm {
+        Norm {
+            subscale: None,
+            sex,
+            min_age: ages.0,
+            max_age: ages.1,
+            bands: vec![
+                band(0, Some(9), &format!("{label} unauffällig"), false),
+                band(10, None, &format!("{label} auffällig"), true),
+            ],
+        }
+    }
+
+    fn label(band: Option<Band>) -> Option<String> {
+        band.map(|b| b.label)
+    }
+
+    fn person(age: Option<u32>, sex: Option<Sex>) -> Demographics {
+        Demographics { age, sex }
+    }
+
+    #[test]
+    fn band_bounds_are_inclusive_and_open_ended() {
+        let test = test(&[], vec![norm(None, (None, None), "alle")]);
+        let anyone = Demographics::default();
+        assert_eq!(
+            label(test.band(None, 0, &anyone)).unwrap(),
+            "alle unauffällig"
+        );
+        assert_eq!(
+            label(test.band(None, 9, &anyone)).unwrap(),
+            "alle unauffällig"
+        );
+        assert_eq!(
+            label(test.band(None, 10, &anyone)).unwrap(),
+            "alle auffällig"
+        );
+        assert!(test.band(None, usize::MAX, &anyone).unwrap().clinical);
+    }
+
+    #[test]
+    fn values_outside_every_band_have_none() {
+        let mut norm = norm(None, (None, None), "alle");
+        norm.bands = vec![band(5, Some(9), "mittel", false)];
+        let test = test(&[], vec![norm]);
+        assert_eq!(test.band(None, 4, &Demographics::default()), None);
+        assert_eq!(test.band(None, 10, &Demographics::default()), None);
+    }
+
+    #[test]
+    fn norms_are_chosen_per_scale() {
+        let mut subscale = norm(None, (None, None), "x");
+        subscale.subscale = Some(String::from("x"));
+        let test = test(
+            &["x", "y"],
+            vec![subscale, norm(None, (None, None), "gesamt")],
+        );
+        let anyone = Demographics::default();
+        assert_eq!(
+            label(test.band(None, 3, &anyone)).unwrap(),
+            "gesamt unauffällig"
+        );
+        assert_eq!(
+            label(test.band(Some("x"), 3, &anyone)).unwrap(),
+            "x unauffällig"
+        );
+        assert_eq!(test.band(Some("y"), 3, &anyone), None);
+    }
+
+    #[test]
+    fn first_applicable_norm_wins() {
+        let test = test(
+            &[],
+            vec![
+                norm(Some(Sex::Female), (Some(18), Some(64)), "Frauen 18-64"),
+                norm(Some(Sex::Female), (None, None), "Frauen"),
+                norm(None, (Some(65), None), "ab 65"),
+                norm(None, (None, None), "alle"),
+            ],
+        );
+        let band = |age, sex| label(test.band(None, 0, &person(age, sex))).unwrap();
+        assert_eq!(
+            band(Some(30), Some(Sex::Female)),
+            "Frauen 18-64 unauffällig"
+        );
+        assert_eq!(band(Some(70), Some(Sex::Female)), "Frauen unauffällig");
+        assert_eq!(band(None, Some(Sex::Female)), "Frauen unauffällig");
+        assert_eq!(band(Some(70), Some(Sex::Male)), "ab 65 unauffällig");
+        assert_eq!(band(Some(65), None), "ab 65 unauffällig");
+        assert_eq!(band(Some(64), Some(Sex::Male)), "alle unauffällig");
+        assert_eq!(band(None, None), "alle unauffällig");
+    }
+
+    #[test]
+    fn restricted_norms_need_known_demographics() {
+        let test = test(
+            &[],
+            vec![
+                norm(Some(Sex::Male), (None, None), "Männer"),
+                norm(None, (Some(18), None), "Erwachsene"),
+            ],
+        );
+        assert_eq!(test.band(None, 0, &person(None, None)), None);
+        assert_eq!(
+            test.band(None, 0, &person(Some(17), Some(Sex::Diverse))),
+            None
+        );
+        assert_eq!(
+            label(test.band(None, 0, &person(Some(18), None))).unwrap(),
+            "Erwachsene unauffällig"
+        );
+    }
+
+    #[test]
+    fn score_looks_up_bands() {
+        let q = question("q", ScoringKey::Forward, 5, &[0, 1, 2]);
+        let results = [TestResultRecord::new(&q, 2).unwrap()];
+        let test = test(&[], vec![norm(None, (None, None), "alle")]);
+        let score = Score::new(&test, &results, &Demographics::default());
+        assert_eq!(score.total, 10);
+        assert!(score.band.unwrap().clinical);
+    }
+
+    #[test]
+    fn demographics_use_the_birth_year() {
+        let info = PatientInfo {
+            birth_year: Some(1990),
+            sex: Some(Sex::Female),
+            ..PatientInfo::default()
+        };
+        let demographics = info.demographics(2026);
+        assert_eq!(demographics.age, Some(36));
+        assert_eq!(demographics.sex, Some(Sex::Female));
+        assert_eq!(info.demographics(1980).age, None);
+    }
 }